@@ -0,0 +1,317 @@
+use crate::database::Database;
+use crate::luhn::{append_check_digit, validate_card_number, CARD_NUMBER_LENGTHS};
+
+use error_stack::{Context, Report, Result, ResultExt};
+use rand::{Rng, RngCore};
+use serde::Serialize;
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum CardNumberGeneratorError {
+  InvalidIssuerRange(String),
+  NoIssuerRanges,
+  NoFreeCardNumber,
+  DatabaseOperationFailed,
+}
+
+pub type CardNumberGeneratorResult<T> = Result<T, CardNumberGeneratorError>;
+
+impl fmt::Display for CardNumberGeneratorError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CardNumberGeneratorError::InvalidIssuerRange(range) => {
+        write!(f, "invalid issuer range \"{range}\", expected format like 400000:16 or 510000-519999:16")
+      },
+      CardNumberGeneratorError::NoIssuerRanges => write!(f, "at least one issuer range is required"),
+      CardNumberGeneratorError::NoFreeCardNumber => write!(f, "no free card number found in issuer ranges"),
+      CardNumberGeneratorError::DatabaseOperationFailed => write!(f, "checking if card number is taken failed"),
+    }
+  }
+}
+
+impl Context for CardNumberGeneratorError {}
+
+/// Number of random card numbers tried before giving up, when ranges are nearly full
+const MAX_ATTEMPTS: usize = 100;
+
+/// Issuer identification numbers from `first` to `last` with the card length,
+/// prefixes are written with `width` digits, so leading zeros are kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IssuerRange {
+  first: u64,
+  last: u64,
+  width: usize,
+  card_length: usize,
+}
+
+impl IssuerRange {
+  /// Random prefix from the range, random account digits and Luhn check digit
+  fn generate(&self, rng: &mut dyn RngCore) -> CardNumberGeneratorResult<String> {
+    let mut card_number = format!("{:0width$}", rng.gen_range(self.first..=self.last), width = self.width);
+
+    while card_number.len() < self.card_length - 1 {
+      card_number.push_str(&rng.gen_range(0..10u32).to_string());
+    }
+
+    append_check_digit(&card_number)
+      .change_context_lazy(|| CardNumberGeneratorError::InvalidIssuerRange(self.to_string()))
+  }
+}
+
+impl fmt::Display for IssuerRange {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:0width$}-{:0width$}:{}", self.first, self.last, self.card_length, width = self.width)
+  }
+}
+
+impl FromStr for IssuerRange {
+  type Err = Report<CardNumberGeneratorError>;
+
+  /// Parses `FIRST[-LAST]:LENGTH`, e.g. "400000:16" or "510000-519999:16"
+  fn from_str(str: &str) -> std::result::Result<Self, Self::Err> {
+    let invalid_range = |reason: &str| {
+      Report::new(CardNumberGeneratorError::InvalidIssuerRange(str.to_owned()))
+        .attach_printable(reason.to_owned())
+    };
+
+    let (prefixes, card_length) = str.split_once(':')
+      .ok_or_else(|| invalid_range("missing card length"))?;
+
+    let (first, last) = prefixes.split_once('-')
+      .unwrap_or((prefixes, prefixes));
+
+    let is_digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    if !is_digits(first) || !is_digits(last) {
+      return Err(invalid_range("prefixes must contain only digits"));
+    }
+
+    if first.len() != last.len() || first > last {
+      return Err(invalid_range("first and last prefix must have the same number of digits, in ascending order"));
+    }
+
+    let card_length = card_length.parse::<usize>()
+      .map_err(|_| invalid_range("card length is not a number"))?;
+
+    if !CARD_NUMBER_LENGTHS.contains(&card_length) {
+      return Err(invalid_range("card length out of range"))
+        .attach_printable(format!("expected {} to {}", CARD_NUMBER_LENGTHS.start(), CARD_NUMBER_LENGTHS.end()));
+    }
+
+    if first.len() >= card_length {
+      return Err(invalid_range("prefix leaves no room for the check digit"));
+    }
+
+    let parse_prefix = |prefix: &str| prefix.parse::<u64>()
+      .map_err(|_| invalid_range("prefix is too long"));
+
+    Ok(IssuerRange {
+      first: parse_prefix(first)?,
+      last: parse_prefix(last)?,
+      width: first.len(),
+      card_length,
+    })
+  }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CardNetwork {
+  Visa,
+  Mastercard,
+  AmericanExpress,
+  Discover,
+  Jcb,
+  DinersClub,
+  UnionPay,
+}
+
+impl fmt::Display for CardNetwork {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CardNetwork::Visa => write!(f, "Visa"),
+      CardNetwork::Mastercard => write!(f, "Mastercard"),
+      CardNetwork::AmericanExpress => write!(f, "American Express"),
+      CardNetwork::Discover => write!(f, "Discover"),
+      CardNetwork::Jcb => write!(f, "JCB"),
+      CardNetwork::DinersClub => write!(f, "Diners Club"),
+      CardNetwork::UnionPay => write!(f, "UnionPay"),
+    }
+  }
+}
+
+/// First and last prefix of the networks' ranges, more specific ranges go first
+const NETWORK_RANGES: &[(&str, &str, CardNetwork)] = &[
+  ("4", "4", CardNetwork::Visa),
+  ("2221", "2720", CardNetwork::Mastercard),
+  ("51", "55", CardNetwork::Mastercard),
+  ("34", "34", CardNetwork::AmericanExpress),
+  ("37", "37", CardNetwork::AmericanExpress),
+  ("6011", "6011", CardNetwork::Discover),
+  ("644", "649", CardNetwork::Discover),
+  ("65", "65", CardNetwork::Discover),
+  ("3528", "3589", CardNetwork::Jcb),
+  ("300", "305", CardNetwork::DinersClub),
+  ("36", "36", CardNetwork::DinersClub),
+  ("38", "39", CardNetwork::DinersClub),
+  ("62", "62", CardNetwork::UnionPay),
+];
+
+fn has_prefix_in(card_number: &str, first: &str, last: &str) -> bool {
+  match card_number.get(..first.len()) {
+    Some(prefix) => first <= prefix && prefix <= last,
+    None => false,
+  }
+}
+
+/// Issues card numbers from the bank's issuer ranges
+#[derive(Clone, Debug)]
+pub struct CardNumberGenerator {
+  ranges: Vec<IssuerRange>,
+}
+
+/// Visa debit range the bank used before ranges were configurable
+impl Default for CardNumberGenerator {
+  fn default() -> Self {
+    CardNumberGenerator {
+      ranges: vec!["400000:16".parse().expect("valid default range")],
+    }
+  }
+}
+
+impl CardNumberGenerator {
+  pub fn new(ranges: Vec<IssuerRange>) -> CardNumberGeneratorResult<Self> {
+    if ranges.is_empty() {
+      return Err(Report::new(CardNumberGeneratorError::NoIssuerRanges));
+    }
+
+    Ok(CardNumberGenerator { ranges })
+  }
+
+  pub fn from_ranges(ranges: &[String]) -> CardNumberGeneratorResult<Self> {
+    let ranges = ranges
+      .iter()
+      .map(|range| range.parse::<IssuerRange>())
+      .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    CardNumberGenerator::new(ranges)
+  }
+
+  /// Random valid card number from one of the ranges, which was never used before
+  pub fn generate(&self, db: &dyn Database, rng: &mut dyn RngCore) -> CardNumberGeneratorResult<String> {
+    for _ in 0..MAX_ATTEMPTS {
+      let range = &self.ranges[rng.gen_range(0..self.ranges.len())];
+      let card_number = range.generate(rng)?;
+      debug_assert!(validate_card_number(&card_number).is_ok());
+
+      let is_used = db.is_card_number_used(&card_number)
+        .change_context(CardNumberGeneratorError::DatabaseOperationFailed)?;
+
+      if !is_used {
+        return Ok(card_number);
+      }
+    }
+
+    Err(Report::new(CardNumberGeneratorError::NoFreeCardNumber))
+      .attach_printable_lazy(|| format!("ranges: {:?}", self.ranges))
+  }
+
+  /// Network of any valid card number, also of cards issued by other banks,
+  /// it doesn't depend on the configured ranges
+  pub fn network(card_number: &str) -> Option<CardNetwork> {
+    let card_number = validate_card_number(card_number).ok()?;
+
+    NETWORK_RANGES
+      .iter()
+      .find(|(first, last, _)| has_prefix_in(&card_number, first, last))
+      .map(|(_, _, network)| *network)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+  use rand::SeedableRng;
+  use rand::rngs::SmallRng;
+
+  backend_tests!(
+    generate_unique_card_numbers,
+  );
+
+  #[test]
+  fn should_parse_issuer_ranges() {
+    let range = "400000:16".parse::<IssuerRange>().unwrap();
+    assert_eq!(range, IssuerRange { first: 400000, last: 400000, width: 6, card_length: 16 });
+
+    let range = "51000000-51009999:19".parse::<IssuerRange>().unwrap();
+    assert_eq!(range, IssuerRange { first: 51000000, last: 51009999, width: 8, card_length: 19 });
+
+    let range = "0012-0099:12".parse::<IssuerRange>().unwrap();
+    assert_eq!(range.to_string(), "0012-0099:12");
+
+    for str in ["", "400000", "400000:", "4000a0:16", "400000-39:16", "499999-400000:16", "400000:11", "400000:20", "-:16", "4000000000000000:16"] {
+      let report = str.parse::<IssuerRange>().unwrap_err();
+      assert!(matches!(report.current_context(), CardNumberGeneratorError::InvalidIssuerRange(_)), "{str}");
+    }
+
+    assert!(CardNumberGenerator::from_ranges(&[]).is_err());
+  }
+
+  #[test]
+  fn should_identify_card_network() {
+    let cases = [
+      ("4000000130085832", Some(CardNetwork::Visa)),
+      ("5555555555554444", Some(CardNetwork::Mastercard)),
+      ("2223003122003222", Some(CardNetwork::Mastercard)),
+      ("378282246310005", Some(CardNetwork::AmericanExpress)),
+      ("6011111111111117", Some(CardNetwork::Discover)),
+      ("3530111333300000", Some(CardNetwork::Jcb)),
+      ("30569309025904", Some(CardNetwork::DinersClub)),
+      ("6200000000000005", Some(CardNetwork::UnionPay)),
+      ("9000000000000006", None),
+      ("4000000130085833", None),
+    ];
+
+    for (card_number, network) in cases {
+      assert_eq!(CardNumberGenerator::network(card_number), network, "{card_number}");
+    }
+  }
+
+  fn generate_unique_card_numbers(mut db: impl Database) {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let ranges = [String::from("400000-400009:16"), String::from("5100000:19")];
+    let generator = CardNumberGenerator::from_ranges(&ranges).unwrap();
+
+    for _ in 0..20 {
+      let card_number = generator.generate(&db, &mut rng).unwrap();
+
+      assert!(validate_card_number(&card_number).is_ok(), "{card_number}");
+
+      match card_number.len() {
+        16 => assert!(has_prefix_in(&card_number, "400000", "400009"), "{card_number}"),
+        19 => assert!(card_number.starts_with("5100000"), "{card_number}"),
+        _ => panic!("unexpected length: {card_number}"),
+      }
+
+      assert!(CardNumberGenerator::network(&card_number).is_some(), "{card_number}");
+
+      let mut client = crate::database::tests::get_mock_client();
+      client.card_number = card_number;
+      db.save_new_client(client).unwrap();
+    }
+
+    assert_eq!(db.get_clients_count().unwrap(), 20);
+
+    // prefix leaves room only for the check digit, so the range has a single card number
+    let generator = CardNumberGenerator::from_ranges(&[String::from("40000000000:12")]).unwrap();
+    let mut client = crate::database::tests::get_mock_client();
+    client.card_number = generator.generate(&db, &mut rng).unwrap();
+    db.save_new_client(client).unwrap();
+
+    let report = generator.generate(&db, &mut rng).unwrap_err();
+    assert!(matches!(report.current_context(), CardNumberGeneratorError::NoFreeCardNumber));
+  }
+}
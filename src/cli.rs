@@ -0,0 +1,545 @@
+use crate::{create_parent_dir, DataBaseType};
+use crate::database::{Database, ErrorKind};
+use crate::database::{json, migration};
+use crate::currency::{Currency, ExchangeRates};
+use crate::card_number::CardNumberGenerator;
+use crate::money::{Money, MoneyError};
+use crate::menu::create_account;
+use crate::luhn::validate_card_number;
+use crate::output::OutputFormat;
+use crate::server;
+
+use clap::{Args, Subcommand};
+use rand::rngs::OsRng;
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use serde_json::json;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct CliError(pub ErrorKind);
+
+pub type CliResult<T> = Result<T, CliError>;
+
+impl fmt::Display for CliError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl Context for CliError {}
+
+impl CliError {
+  /// Process exit code, 2 matches the code clap uses for invalid usage
+  pub fn exit_code(&self) -> i32 {
+    match self.0 {
+      ErrorKind::InvalidAmount | ErrorKind::InvalidArgument => 2,
+      ErrorKind::ClientNotFound => 3,
+      ErrorKind::InsufficientFunds => 4,
+      ErrorKind::CurrencyConversionFailed => 5,
+      ErrorKind::ClientAlreadyExists
+      | ErrorKind::InvalidCredentials
+      | ErrorKind::CardLocked
+      | ErrorKind::Other => 1,
+    }
+  }
+
+  fn from_report<C>(report: Report<C>) -> Report<CliError> {
+    let kind = ErrorKind::of(&report);
+
+    report.change_context(CliError(kind))
+  }
+}
+
+/// Single operations for scripts, without the interactive menu
+#[derive(Subcommand)]
+pub enum Command {
+  #[clap(flatten)]
+  Database(DatabaseCommand),
+  /// Copy all clients and their history from one database type to another
+  Migrate(MigrateArgs),
+}
+
+/// Operations on the database selected by the global options
+#[derive(Subcommand)]
+pub enum DatabaseCommand {
+  /// Create account, prints its card number and PIN
+  CreateAccount {
+    /// ISO 4217 code of the account currency
+    #[clap(long, default_value = "PLN", value_parser)]
+    currency: String,
+  },
+  /// Print balance of the account
+  Balance {
+    #[clap(long, value_parser)]
+    card: String,
+  },
+  /// Add funds to the account
+  Deposit {
+    #[clap(long, value_parser)]
+    card: String,
+    #[clap(long, value_parser)]
+    amount: String,
+  },
+  /// Transfer funds, amount is in the sender currency
+  Transfer {
+    #[clap(long, value_parser)]
+    from: String,
+    #[clap(long, value_parser)]
+    to: String,
+    #[clap(long, value_parser)]
+    amount: String,
+  },
+  /// Close the account, prints its final balance
+  Close {
+    #[clap(long, value_parser)]
+    card: String,
+  },
+  /// Unlock card locked after too many wrong PINs
+  Unlock {
+    #[clap(long, value_parser)]
+    card: String,
+  },
+  /// Serve JSON REST API over HTTP until the process is killed
+  Serve {
+    #[clap(long, default_value = "127.0.0.1:8080", value_parser)]
+    address: String,
+  },
+}
+
+#[derive(Args)]
+pub struct MigrateArgs {
+  #[clap(long, arg_enum, value_parser)]
+  from: DataBaseType,
+  #[clap(long, arg_enum, value_parser)]
+  to: DataBaseType,
+  /// Source database file [default: default file of the type in DATA_DIR]
+  #[clap(long, value_name = "FILE", value_parser)]
+  from_path: Option<PathBuf>,
+  /// Target database file, created when missing [default: default file of the type in DATA_DIR]
+  #[clap(long, value_name = "FILE", value_parser)]
+  to_path: Option<PathBuf>,
+  /// Replace target database file which already contains data, once the migration succeeded
+  #[clap(long, value_parser)]
+  force: bool,
+}
+
+impl Command {
+  pub fn name(&self) -> &str {
+    match self {
+      Command::Database(command) => command.name(),
+      Command::Migrate(_) => "migrate",
+    }
+  }
+}
+
+impl DatabaseCommand {
+  pub fn name(&self) -> &str {
+    match self {
+      DatabaseCommand::CreateAccount { .. } => "create-account",
+      DatabaseCommand::Balance { .. } => "balance",
+      DatabaseCommand::Deposit { .. } => "deposit",
+      DatabaseCommand::Transfer { .. } => "transfer",
+      DatabaseCommand::Close { .. } => "close",
+      DatabaseCommand::Unlock { .. } => "unlock",
+      DatabaseCommand::Serve { .. } => "serve",
+    }
+  }
+
+  pub fn exec(
+    &self,
+    db: &mut dyn Database,
+    rates: &ExchangeRates,
+    card_numbers: &CardNumberGenerator,
+    max_pin_attempts: u32,
+    output: OutputFormat
+  ) -> CliResult<()> {
+    match self {
+      DatabaseCommand::CreateAccount { currency } => {
+        let currency = currency.to_uppercase().parse::<Currency>()
+          .map_err(CliError::from_report)?;
+
+        let new_account = create_account(db, currency, card_numbers, &mut OsRng)
+          .map_err(CliError::from_report)?;
+
+        output.print_ok(
+          self.name(),
+          json!({
+            "card_number": new_account.card_number,
+            "pin": new_account.pin,
+            "currency": new_account.currency,
+            "network": new_account.network,
+          }),
+          &format!(
+            "card_number: {}\npin: {}\ncurrency: {}",
+            new_account.card_number,
+            new_account.pin,
+            new_account.currency
+          )
+        );
+      },
+      DatabaseCommand::Balance { card } => {
+        self.print_balance(db, card, output)?;
+      },
+      DatabaseCommand::Deposit { card, amount } => {
+        let amount = parse_amount(amount)?;
+
+        db.add_funds(amount, card)
+          .map_err(CliError::from_report)?;
+
+        self.print_balance(db, card, output)?;
+      },
+      DatabaseCommand::Transfer { from, to, amount } => {
+        let to = validate_card_number(to)
+          .attach_printable_lazy(|| format!("receiver_card_number: \"{}\"", to))
+          .map_err(CliError::from_report)?;
+        let amount = parse_amount(amount)?;
+
+        db.transfer_funds(amount, from, &to, rates)
+          .attach_printable_lazy(|| {
+            format!("amount: {} sender_card_number: {} receiver_card_number: {}", amount, from, to)
+          })
+          .map_err(CliError::from_report)?;
+
+        self.print_balance(db, from, output)?;
+      },
+      DatabaseCommand::Close { card } => {
+        let client = db.remove_client(card)
+          .map_err(CliError::from_report)?;
+
+        output.print_ok(
+          self.name(),
+          json!({ "balance": client.balance, "currency": client.currency }),
+          &format!("{} {}", client.balance, client.currency)
+        );
+      },
+      DatabaseCommand::Unlock { card } => {
+        db.get_client(card)
+          .map_err(CliError::from_report)?;
+
+        db.reset_login_attempts(card)
+          .map_err(CliError::from_report)?;
+
+        output.print_ok(
+          self.name(),
+          json!({ "card_number": card }),
+          &format!("Card {} unlocked", card)
+        );
+      },
+      DatabaseCommand::Serve { address } => {
+        server::serve(db, rates, card_numbers, max_pin_attempts, address)
+          .map_err(CliError::from_report)?;
+      },
+    }
+
+    Ok(())
+  }
+
+  fn print_balance(&self, db: &dyn Database, card_number: &str, output: OutputFormat) -> CliResult<()> {
+    let client = db.get_client(card_number)
+      .map_err(CliError::from_report)?;
+
+    output.print_ok(
+      self.name(),
+      json!({ "balance": client.balance, "currency": client.currency }),
+      &format!("{} {}", client.balance, client.currency)
+    );
+
+    Ok(())
+  }
+}
+
+impl MigrateArgs {
+  pub fn exec(
+    &self,
+    data_dir: &Path,
+    open_db: impl Fn(DataBaseType, &Path) -> Box<dyn Database>,
+    output: OutputFormat
+  ) -> CliResult<()> {
+    let from_path = self.from_path.clone()
+      .unwrap_or_else(|| data_dir.join(self.from.default_file_name()));
+    let to_path = self.to_path.clone()
+      .unwrap_or_else(|| data_dir.join(self.to.default_file_name()));
+
+    if from_path == to_path {
+      return Err(Report::new(CliError(ErrorKind::InvalidArgument)))
+        .attach_printable(format!("source and target are the same file: {}", from_path.display()));
+    }
+
+    if !self.to.creates_file() {
+      return Err(Report::new(CliError(ErrorKind::InvalidArgument)))
+        .attach_printable("memory database is discarded on exit, it can't be migration target");
+    }
+
+    if !from_path.exists() {
+      return Err(Report::new(CliError(ErrorKind::InvalidArgument)))
+        .attach_printable(format!("source database file does not exist: {}", from_path.display()));
+    }
+
+    create_parent_dir(&to_path)
+      .report()
+      .change_context(CliError(ErrorKind::Other))
+      .attach_printable("creating target database directory failed")?;
+
+    // forced target is replaced only after the migration into a staging file succeeded
+    let staging_path = (self.force && to_path.exists()).then(|| path_with_suffix(&to_path, ".migrating"));
+    let migration_path = staging_path.as_deref().unwrap_or(&to_path);
+
+    if let Some(staging_path) = &staging_path {
+      remove_staging_files(staging_path)?;
+    }
+
+    let result = {
+      let source = open_db(self.from, &from_path);
+      let mut target = open_db(self.to, migration_path);
+
+      migration::migrate(source.as_ref(), target.as_mut())
+        .attach_printable_lazy(|| {
+          format!("from: {}, to: {}", from_path.display(), to_path.display())
+        })
+        .map_err(CliError::from_report)
+    };
+
+    let summary = match (result, &staging_path) {
+      (Ok(summary), Some(staging_path)) => {
+        replace_database_file(staging_path, &to_path)?;
+        summary
+      },
+      (Ok(summary), None) => summary,
+      (Err(report), Some(staging_path)) => {
+        let _ = remove_staging_files(staging_path);
+        return Err(report);
+      },
+      (Err(report), None) => return Err(report),
+    };
+
+    let total_balances: Vec<String> = summary.total_balances
+      .iter()
+      .map(|(currency, total)| format!("{} {}", total, currency))
+      .collect();
+
+    output.print_ok(
+      "migrate",
+      json!({
+        "clients": summary.clients,
+        "locked_cards": summary.locked_cards,
+        "transactions": summary.transactions,
+        "total_balances": summary.total_balances,
+      }),
+      &format!(
+        "Migrated {} clients ({} locked cards) and {} transactions\ntotal balances: {}",
+        summary.clients,
+        summary.locked_cards,
+        summary.transactions,
+        total_balances.join(", ")
+      )
+    );
+
+    Ok(())
+  }
+}
+
+fn parse_amount(amount_str: &str) -> CliResult<Money> {
+  let amount = amount_str.parse::<Money>()
+    .map_err(CliError::from_report)?;
+
+  if !amount.is_positive() {
+    return Err(Report::new(MoneyError::NotPositive))
+      .attach_printable(format!("amount: \"{}\"", amount_str))
+      .map_err(CliError::from_report);
+  }
+
+  Ok(amount)
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+  let mut path = path.as_os_str().to_owned();
+  path.push(suffix);
+
+  PathBuf::from(path)
+}
+
+fn remove_file_if_exists(path: &Path) -> CliResult<()> {
+  match std::fs::remove_file(path) {
+    Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err)
+      .report()
+      .change_context(CliError(ErrorKind::Other))
+      .attach_printable(format!("failed to delete file: {}", path.display())),
+    _ => Ok(()),
+  }
+}
+
+/// Staging file with everything databases keep next to it: JSON backups, lock and unfinished write, SQLite journal
+fn remove_staging_files(staging_path: &Path) -> CliResult<()> {
+  let sidecars = [".lock", ".tmp", "-journal"]
+    .iter()
+    .map(|suffix| path_with_suffix(staging_path, suffix));
+
+  for path in json::backup_paths(staging_path).into_iter().chain(sidecars) {
+    remove_file_if_exists(&path)?;
+  }
+
+  remove_file_if_exists(staging_path)
+}
+
+/// Backups of the replaced target are removed first, so recovering a broken file
+/// can't bring back data from before the migration
+fn replace_database_file(staging_path: &Path, to_path: &Path) -> CliResult<()> {
+  for backup in json::backup_paths(to_path) {
+    remove_file_if_exists(&backup)?;
+  }
+
+  std::fs::rename(staging_path, to_path)
+    .report()
+    .change_context(CliError(ErrorKind::Other))
+    .attach_printable_lazy(|| {
+      format!("failed to replace target database file: {}", to_path.display())
+    })?;
+
+  remove_staging_files(staging_path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+  use crate::database::{backup_paths, JsonDb};
+
+  backend_tests!(
+    exec_commands,
+    map_errors_to_exit_codes,
+  );
+
+  #[test]
+  fn should_replace_forced_target_only_after_migration() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-migrate-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let from_path = dir.join("source.json");
+    let to_path = dir.join("target.json");
+
+    let migrate_args = MigrateArgs {
+      from: DataBaseType::JSON,
+      to: DataBaseType::JSON,
+      from_path: Some(from_path.clone()),
+      to_path: Some(to_path.clone()),
+      force: true,
+    };
+
+    let mut source = JsonDb::new(&from_path);
+    source.save_new_client(crate::database::tests::get_mock_client()).unwrap();
+    drop(source);
+
+    let mut target = JsonDb::new(&to_path);
+    for card_number in ["4000000000000002", "4000000000000010"] {
+      let mut client = crate::database::tests::get_mock_client();
+      client.card_number = card_number.to_owned();
+      target.save_new_client(client).unwrap();
+    }
+    drop(target);
+    assert!(backup_paths(&to_path)[0].exists());
+
+    // migration into the staging file fails, because something writes into it first
+    let open_dirty_target = |database, path: &Path| {
+      let mut db = crate::db_factory(database, path);
+      if path != from_path {
+        db.save_new_client(crate::database::tests::get_mock_client()).unwrap();
+      }
+      db
+    };
+
+    assert!(migrate_args.exec(&dir, open_dirty_target, OutputFormat::Text).is_err());
+    assert_eq!(JsonDb::new(&to_path).get_clients_count().unwrap(), 2);
+    assert!(backup_paths(&to_path)[0].exists());
+
+    migrate_args.exec(&dir, crate::db_factory, OutputFormat::Text).unwrap();
+    assert_eq!(JsonDb::new(&to_path).get_clients_count().unwrap(), 1);
+    assert!(backup_paths(&to_path).iter().all(|backup| !backup.exists()));
+
+    let leftovers: Vec<_> = std::fs::read_dir(&dir).unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .filter(|name| name.contains("migrating"))
+      .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  fn exec(command: DatabaseCommand, db: &mut dyn Database) -> CliResult<()> {
+    command.exec(db, &ExchangeRates::default(), &CardNumberGenerator::default(), 3, OutputFormat::Text)
+  }
+
+  fn exec_commands(mut db: impl Database) {
+    let sender = crate::database::tests::get_mock_client();
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000002");
+
+    let sender_card_number = sender.card_number.clone();
+    let receiver_card_number = receiver.card_number.clone();
+
+    db.save_new_client(sender).unwrap();
+    db.save_new_client(receiver).unwrap();
+
+    exec(DatabaseCommand::CreateAccount { currency: String::from("eur") }, &mut db).unwrap();
+    assert_eq!(db.get_clients_count().unwrap(), 3);
+
+    exec(DatabaseCommand::Deposit {
+      card: sender_card_number.clone(),
+      amount: String::from("100.50"),
+    }, &mut db).unwrap();
+
+    exec(DatabaseCommand::Transfer {
+      from: sender_card_number.clone(),
+      to: receiver_card_number.clone(),
+      amount: String::from("0.50"),
+    }, &mut db).unwrap();
+
+    assert_eq!(db.get_client(&sender_card_number).unwrap().balance.to_string(), "100.00");
+    assert_eq!(db.get_client(&receiver_card_number).unwrap().balance.to_string(), "0.50");
+
+    exec(DatabaseCommand::Balance { card: receiver_card_number.clone() }, &mut db).unwrap();
+    exec(DatabaseCommand::Close { card: receiver_card_number.clone() }, &mut db).unwrap();
+    assert!(!db.has_client(&receiver_card_number).unwrap());
+  }
+
+  fn map_errors_to_exit_codes(mut db: impl Database) {
+    let mut sender = crate::database::tests::get_mock_client();
+    sender.balance = Money::from_minor_units(100);
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000002");
+    let mut eur_receiver = crate::database::tests::get_mock_client();
+    eur_receiver.card_number = String::from("4000000000000010");
+    eur_receiver.currency = "EUR".parse().unwrap();
+
+    let sender_card_number = sender.card_number.clone();
+    let receiver_card_number = receiver.card_number.clone();
+    let eur_receiver_card_number = eur_receiver.card_number.clone();
+
+    db.save_new_client(sender).unwrap();
+    db.save_new_client(receiver).unwrap();
+    db.save_new_client(eur_receiver).unwrap();
+
+    let transfer = |amount: &str, to: &str| DatabaseCommand::Transfer {
+      from: sender_card_number.clone(),
+      to: to.to_owned(),
+      amount: amount.to_owned(),
+    };
+
+    let cases = [
+      (transfer("abc", &receiver_card_number), 2),
+      (transfer("-1", &receiver_card_number), 2),
+      (transfer("1", "4000000000000028"), 3),
+      (transfer("1", "4000000000000003"), 2),
+      (transfer("1", &sender_card_number), 2),
+      (transfer("2", &receiver_card_number), 4),
+      (transfer("1", &eur_receiver_card_number), 5),
+      (DatabaseCommand::Balance { card: String::from("4000000000000028") }, 3),
+      (DatabaseCommand::CreateAccount { currency: String::from("EURO") }, 2),
+    ];
+
+    for (command, exit_code) in cases {
+      let report = exec(command, &mut db).unwrap_err();
+      assert_eq!(report.current_context().exit_code(), exit_code, "{report:?}");
+    }
+  }
+}
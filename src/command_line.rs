@@ -1,3 +1,5 @@
+use crate::output::OutputFormat;
+
 use error_stack::{Context, IntoReport, Result, ResultExt};
 
 use std::fmt;
@@ -15,8 +17,8 @@ impl fmt::Display for CommandLineError {
 
 impl Context for CommandLineError {}
 
-pub fn read_with_prompt(prompt: &str) -> CommandLineResult<String> {
-  println!("{}", prompt);
+pub fn read_with_prompt(prompt: &str, output: OutputFormat) -> CommandLineResult<String> {
+  output.print_text(prompt);
 
   let mut buf = String::new();
   std::io::stdin()
@@ -0,0 +1,314 @@
+use crate::money::Money;
+
+use serde::{Deserialize, Serialize};
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum CurrencyError {
+  InvalidCode(String),
+  InvalidRate(String),
+  MissingRate(Currency, Currency),
+  ConversionOverflow,
+  ReadingRatesFile,
+  ParsingRatesFile,
+}
+
+pub type CurrencyResult<T> = Result<T, CurrencyError>;
+
+impl fmt::Display for CurrencyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CurrencyError::InvalidCode(code) => write!(f, "invalid currency code \"{code}\", expected ISO 4217 code like PLN"),
+      CurrencyError::InvalidRate(rate) => write!(f, "invalid exchange rate \"{rate}\""),
+      CurrencyError::MissingRate(from, to) => write!(f, "no exchange rate from {from} to {to}"),
+      CurrencyError::ConversionOverflow => write!(f, "converted amount is too large"),
+      CurrencyError::ReadingRatesFile => write!(f, "reading exchange rates file failed"),
+      CurrencyError::ParsingRatesFile => write!(f, "exchange rates file is malformed"),
+    }
+  }
+}
+
+impl Context for CurrencyError {}
+
+/// ISO 4217 currency code
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+  pub const PLN: Currency = Currency(*b"PLN");
+
+  pub fn as_str(&self) -> &str {
+    std::str::from_utf8(&self.0).expect("currency code is ASCII")
+  }
+}
+
+/// Accounts created before currencies were introduced are kept in PLN
+impl Default for Currency {
+  fn default() -> Self {
+    Currency::PLN
+  }
+}
+
+impl fmt::Display for Currency {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for Currency {
+  type Err = Report<CurrencyError>;
+
+  fn from_str(code: &str) -> std::result::Result<Self, Self::Err> {
+    match <[u8; 3]>::try_from(code.as_bytes()) {
+      Ok(bytes) if bytes.iter().all(u8::is_ascii_uppercase) => Ok(Currency(bytes)),
+      _ => Err(Report::new(CurrencyError::InvalidCode(code.to_owned()))),
+    }
+  }
+}
+
+impl TryFrom<String> for Currency {
+  type Error = String;
+
+  fn try_from(code: String) -> std::result::Result<Self, Self::Error> {
+    code.parse()
+      .map_err(|report: Report<CurrencyError>| report.current_context().to_string())
+  }
+}
+
+impl From<Currency> for String {
+  fn from(currency: Currency) -> Self {
+    currency.to_string()
+  }
+}
+
+const RATE_SCALE: i128 = 1_000_000;
+const RATE_DECIMAL_PLACES: usize = 6;
+
+/// Exchange rate as fixed point number with 6 decimal places,
+/// amount in the target currency = amount * rate
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rate(i64);
+
+impl Rate {
+  pub const fn from_micro_units(micro_units: i64) -> Self {
+    Rate(micro_units)
+  }
+
+  pub const fn micro_units(self) -> i64 {
+    self.0
+  }
+
+  fn inverse(self) -> Rate {
+    let rate = self.0 as i128;
+
+    Rate(((RATE_SCALE * RATE_SCALE + rate / 2) / rate) as i64)
+  }
+
+  /// Converted amount rounded half away from zero to minor units
+  pub fn convert(self, amount: Money) -> Option<Money> {
+    let scaled = (amount.minor_units() as i128).checked_mul(self.0 as i128)?;
+    let half = RATE_SCALE / 2 * scaled.signum();
+    let converted = (scaled + half) / RATE_SCALE;
+
+    i64::try_from(converted).ok().map(Money::from_minor_units)
+  }
+}
+
+impl fmt::Display for Rate {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let scale = RATE_SCALE as i64;
+    let fraction = format!("{:0width$}", self.0 % scale, width = RATE_DECIMAL_PLACES);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+      write!(f, "{}", self.0 / scale)
+    } else {
+      write!(f, "{}.{}", self.0 / scale, fraction)
+    }
+  }
+}
+
+impl FromStr for Rate {
+  type Err = Report<CurrencyError>;
+
+  fn from_str(str: &str) -> std::result::Result<Self, Self::Err> {
+    let invalid_rate = || Report::new(CurrencyError::InvalidRate(str.to_owned()));
+
+    let (units_str, fraction_str) = str.split_once('.').unwrap_or((str, ""));
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+
+    if units_str.is_empty()
+      || fraction_str.len() > RATE_DECIMAL_PLACES
+      || !is_digits(units_str)
+      || !is_digits(fraction_str)
+    {
+      return Err(invalid_rate());
+    }
+
+    let fraction = format!("{:0<width$}", fraction_str, width = RATE_DECIMAL_PLACES);
+
+    let rate = units_str.parse::<i64>().ok()
+      .and_then(|units| units.checked_mul(RATE_SCALE as i64))
+      .zip(fraction.parse::<i64>().ok())
+      .and_then(|(units, fraction)| units.checked_add(fraction))
+      .ok_or_else(invalid_rate)?;
+
+    if rate == 0 {
+      return Err(invalid_rate());
+    }
+
+    Ok(Rate(rate))
+  }
+}
+
+impl TryFrom<String> for Rate {
+  type Error = String;
+
+  fn try_from(rate: String) -> std::result::Result<Self, Self::Error> {
+    rate.parse()
+      .map_err(|report: Report<CurrencyError>| report.current_context().to_string())
+  }
+}
+
+impl From<Rate> for String {
+  fn from(rate: Rate) -> Self {
+    rate.to_string()
+  }
+}
+
+/// Local exchange rates table, `{"EUR": {"PLN": "4.32"}}` means 1 EUR = 4.32 PLN.
+/// Missing rates are derived from the opposite direction.
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ExchangeRates {
+  rates: BTreeMap<Currency, BTreeMap<Currency, Rate>>,
+}
+
+impl ExchangeRates {
+  /// Missing file means there are no rates, so only same currency transfers are possible
+  pub fn load(path: &Path) -> CurrencyResult<Self> {
+    if !path.exists() {
+      return Ok(ExchangeRates::default());
+    }
+
+    let json = std::fs::read_to_string(path)
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to read file {}", path.display())
+      })
+      .change_context(CurrencyError::ReadingRatesFile)?;
+
+    ExchangeRates::from_json(&json)
+      .attach_printable_lazy(|| {
+        format!("file: {}", path.display())
+      })
+  }
+
+  pub fn from_json(json: &str) -> CurrencyResult<Self> {
+    serde_json::from_str(json)
+      .report()
+      .change_context(CurrencyError::ParsingRatesFile)
+  }
+
+  pub fn get_rate(&self, from: Currency, to: Currency) -> CurrencyResult<Rate> {
+    let direct = self.rates
+      .get(&from)
+      .and_then(|rates| rates.get(&to));
+
+    if let Some(rate) = direct {
+      return Ok(*rate);
+    }
+
+    self.rates
+      .get(&to)
+      .and_then(|rates| rates.get(&from))
+      .map(|rate| rate.inverse())
+      .ok_or_else(|| Report::new(CurrencyError::MissingRate(from, to)))
+  }
+
+  /// Returns converted amount and the rate used, no rate is used for the same currency
+  pub fn convert(&self, amount: Money, from: Currency, to: Currency) -> CurrencyResult<(Money, Option<Rate>)> {
+    if from == to {
+      return Ok((amount, None));
+    }
+
+    let rate = self.get_rate(from, to)?;
+
+    let converted = rate.convert(amount)
+      .ok_or_else(|| Report::new(CurrencyError::ConversionOverflow))
+      .attach_printable_lazy(|| {
+        format!("amount: {amount} {from}, rate: {rate}")
+      })?;
+
+    Ok((converted, Some(rate)))
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  pub fn get_mock_rates() -> ExchangeRates {
+    ExchangeRates::from_json(r#"{
+      "EUR": { "PLN": "4.32", "USD": "1.08" },
+      "USD": { "PLN": "4" }
+    }"#).unwrap()
+  }
+
+  #[test]
+  fn should_parse_currency_codes() {
+    assert_eq!("EUR".parse::<Currency>().unwrap().to_string(), "EUR");
+
+    for code in ["", "eur", "EU", "EURO", "E1R"] {
+      assert!(code.parse::<Currency>().is_err(), "{code}");
+    }
+  }
+
+  #[test]
+  fn should_parse_rates() {
+    assert_eq!("4.32".parse::<Rate>().unwrap(), Rate::from_micro_units(4_320_000));
+    assert_eq!("4".parse::<Rate>().unwrap().to_string(), "4");
+    assert_eq!("0.231481".parse::<Rate>().unwrap().to_string(), "0.231481");
+
+    for rate in ["", "0", "-1", "1.1234567", "1,5", ".5"] {
+      assert!(rate.parse::<Rate>().is_err(), "{rate}");
+    }
+  }
+
+  #[test]
+  fn should_convert_amounts() {
+    let rates = get_mock_rates();
+    let eur: Currency = "EUR".parse().unwrap();
+    let usd: Currency = "USD".parse().unwrap();
+
+    let (pln, rate) = rates.convert(Money::from_minor_units(1000), eur, Currency::PLN).unwrap();
+    assert_eq!(pln, Money::from_minor_units(4320));
+    assert_eq!(rate, Some(Rate::from_micro_units(4_320_000)));
+
+    let (eur_amount, rate) = rates.convert(Money::from_minor_units(432), Currency::PLN, eur).unwrap();
+    assert_eq!(eur_amount, Money::from_minor_units(100));
+    assert_eq!(rate, Some(Rate::from_micro_units(231_481)));
+
+    let (same, rate) = rates.convert(Money::from_minor_units(1000), usd, usd).unwrap();
+    assert_eq!(same, Money::from_minor_units(1000));
+    assert_eq!(rate, None);
+
+    let gbp: Currency = "GBP".parse().unwrap();
+    let report = rates.convert(Money::from_minor_units(1000), gbp, usd).unwrap_err();
+    assert!(matches!(report.current_context(), CurrencyError::MissingRate(_, _)));
+  }
+
+  #[test]
+  fn should_load_missing_rates_file_as_empty() {
+    let rates = ExchangeRates::load(Path::new("missing-rates-file.json")).unwrap();
+
+    assert_eq!(rates, ExchangeRates::default());
+  }
+}
@@ -1,23 +1,113 @@
 pub mod json;
 pub mod sqlite;
+pub mod memory;
+pub mod migration;
+#[cfg(test)]
+mod conformance;
+
+use crate::money::{Money, MoneyError};
+use crate::currency::{Currency, CurrencyError, ExchangeRates, Rate};
+use crate::pin::PinError;
+use crate::luhn::CardNumberError;
 
 use serde::{Deserialize, Serialize};
-use error_stack::{Context, Result};
+use error_stack::{Context, Report, Result};
+
+use std::collections::BTreeMap;
 
 pub use sqlite::*;
 pub use json::*;
+pub use memory::*;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Client {
   pub card_number: String,
+  /// Argon2 hash of the PIN, legacy records keep it in plaintext until next login
   pub pin: String,
-  pub balance: i32,
+  pub balance: Money,
+  #[serde(default)]
+  pub currency: Currency,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransactionKind {
+  Deposit,
+  TransferIn,
+  TransferOut,
+  AccountClosed,
+}
+
+impl std::fmt::Display for TransactionKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      TransactionKind::Deposit => write!(f, "deposit"),
+      TransactionKind::TransferIn => write!(f, "transfer in"),
+      TransactionKind::TransferOut => write!(f, "transfer out"),
+      TransactionKind::AccountClosed => write!(f, "account closed"),
+    }
+  }
+}
+
+/// Immutable ledger entry, `balance` is the client balance after the operation,
+/// `amount` and `balance` are in the currency of the client
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Transaction {
+  pub id: u64,
+  pub timestamp: u64,
+  pub card_number: String,
+  pub kind: TransactionKind,
+  pub amount: Money,
+  pub counterparty: Option<String>,
+  pub balance: Money,
+  /// Rate used to convert transfer between accounts in different currencies
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub exchange_rate: Option<Rate>,
+}
+
+impl Transaction {
+  /// New ledger entry, `id` is assigned by the database when the entry is saved
+  pub fn new(card_number: &str, kind: TransactionKind, amount: Money, balance: Money) -> Self {
+    Transaction {
+      id: 0,
+      timestamp: current_timestamp(),
+      card_number: card_number.to_owned(),
+      kind,
+      amount,
+      counterparty: None,
+      balance,
+      exchange_rate: None,
+    }
+  }
+
+  pub fn with_counterparty(mut self, card_number: &str, exchange_rate: Option<Rate>) -> Self {
+    self.counterparty = Some(card_number.to_owned());
+    self.exchange_rate = exchange_rate;
+    self
+  }
+}
+
+/// Consecutive failed logins of the card, `locked` stays set until an admin unlocks the card
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoginAttempts {
+  pub failed: u32,
+  pub locked: bool,
+}
+
+/// Whole content of the database, used to copy data between backends
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+  pub clients: Vec<Client>,
+  /// Failed logins of the cards which have any
+  pub login_attempts: BTreeMap<String, LoginAttempts>,
+  /// Ledger entries of all cards including closed accounts, oldest first
+  pub transactions: Vec<Transaction>,
 }
 
 #[derive(Debug)]
 pub enum DatabaseError {
   JSON,
   SQLite,
+  Memory,
 }
 
 impl std::fmt::Display for DatabaseError {
@@ -30,26 +120,159 @@ impl Context for DatabaseError {}
 
 pub type DatabaseResult<T> = Result<T, DatabaseError>;
 
+/// Backend independent reason of failed operation, reported to scripts as exit code or JSON
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+  ClientNotFound,
+  ClientAlreadyExists,
+  InsufficientFunds,
+  InvalidAmount,
+  InvalidArgument,
+  InvalidCredentials,
+  CardLocked,
+  CurrencyConversionFailed,
+  Other,
+}
+
+impl std::fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ErrorKind::ClientNotFound => write!(f, "client not found"),
+      ErrorKind::ClientAlreadyExists => write!(f, "client already exists"),
+      ErrorKind::InsufficientFunds => write!(f, "insufficient funds"),
+      ErrorKind::InvalidAmount => write!(f, "invalid amount"),
+      ErrorKind::InvalidArgument => write!(f, "invalid argument"),
+      ErrorKind::InvalidCredentials => write!(f, "invalid card number or PIN"),
+      ErrorKind::CardLocked => write!(f, "card is locked"),
+      ErrorKind::CurrencyConversionFailed => write!(f, "currency conversion failed"),
+      ErrorKind::Other => write!(f, "operation failed"),
+    }
+  }
+}
+
+impl ErrorKind {
+  /// Finds the first known error in the report, also when it was wrapped into other context
+  pub fn of<C>(report: &Report<C>) -> Self {
+    if let Some(error) = report.downcast_ref::<JsonDatabaseError>() {
+      return error.kind();
+    }
+
+    if let Some(error) = report.downcast_ref::<SQLiteDatabaseError>() {
+      return error.kind();
+    }
+
+    if let Some(error) = report.downcast_ref::<MemoryDatabaseError>() {
+      return error.kind();
+    }
+
+    if report.contains::<MoneyError>() {
+      return ErrorKind::InvalidAmount;
+    }
+
+    if let Some(error) = report.downcast_ref::<CurrencyError>() {
+      return match error {
+        CurrencyError::InvalidCode(_) | CurrencyError::InvalidRate(_) => ErrorKind::InvalidArgument,
+        CurrencyError::MissingRate(_, _) | CurrencyError::ConversionOverflow => ErrorKind::CurrencyConversionFailed,
+        _ => ErrorKind::Other,
+      };
+    }
+
+    if let Some(PinError::InvalidFormat | PinError::TooWeak) = report.downcast_ref::<PinError>() {
+      return ErrorKind::InvalidArgument;
+    }
+
+    if report.contains::<CardNumberError>() {
+      return ErrorKind::InvalidArgument;
+    }
+
+    ErrorKind::Other
+  }
+}
+
 pub trait Database {
   fn name(&self) -> &str;
   fn save_new_client(&mut self, client: Client) -> DatabaseResult<()>;
   fn has_client(&self, card_number: &str) -> DatabaseResult<bool>;
   fn get_client(&self, card_number: &str) -> DatabaseResult<Client>;
   fn remove_client(&mut self, card_number: &str) -> DatabaseResult<Client>;
-  fn add_funds(&mut self, funds: u32, card_number: &str) -> DatabaseResult<()>;
-  fn transfer_funds(&mut self, funds: u32, sender_card_number: &str, receiver_card_number: &str) -> DatabaseResult<()>;
-  fn get_clients_count(&self) -> DatabaseResult<u32>; // TODO remove, used only in tests
+  fn add_funds(&mut self, funds: Money, card_number: &str) -> DatabaseResult<()>;
+  /// Replaces stored PIN, callers pass already hashed PIN
+  fn update_pin(&mut self, card_number: &str, pin: &str) -> DatabaseResult<()>;
+  /// `funds` are in sender's currency, receiver gets them converted with `rates`
+  fn transfer_funds(
+    &mut self,
+    funds: Money,
+    sender_card_number: &str,
+    receiver_card_number: &str,
+    rates: &ExchangeRates
+  ) -> DatabaseResult<()>;
+  fn get_login_attempts(&self, card_number: &str) -> DatabaseResult<LoginAttempts>;
+  /// Increments failed logins counter and locks the card when it reaches `max_attempts`
+  fn register_failed_login(&mut self, card_number: &str, max_attempts: u32) -> DatabaseResult<LoginAttempts>;
+  /// Clears failed logins counter and unlocks the card
+  fn reset_login_attempts(&mut self, card_number: &str) -> DatabaseResult<()>;
+  fn get_clients_count(&self) -> DatabaseResult<u32>;
+  /// Ledger entries of the card, oldest first
+  fn get_history(&self, card_number: &str) -> DatabaseResult<Vec<Transaction>>;
+  /// Card number belongs to a client or still has ledger entries of a closed account,
+  /// such numbers are never issued again, so the new owner doesn't see the old history
+  fn is_card_number_used(&self, card_number: &str) -> DatabaseResult<bool> {
+    Ok(self.has_client(card_number)? || !self.get_history(card_number)?.is_empty())
+  }
+  fn export(&self) -> DatabaseResult<Snapshot>;
+  /// Saves all snapshot data at once, fails without changes when any of the clients already exists.
+  /// Ledger entries keep their order and timestamps, ids are assigned by the database.
+  fn import(&mut self, snapshot: Snapshot) -> DatabaseResult<()>;
+}
+
+/// Seconds since UNIX epoch, used as ledger entry timestamp
+pub fn current_timestamp() -> u64 {
+  use std::time::{SystemTime, UNIX_EPOCH};
+
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or(0)
 }
 
 #[allow(dead_code)]
 pub mod tests {
   use crate::Client;
+  use crate::money::Money;
+  use crate::currency::Currency;
 
   pub fn get_mock_client() -> Client {
     Client {
       card_number: String::from("4000000000000000"),
       pin: String::from("1234"),
-      balance: 0
+      balance: Money::ZERO,
+      currency: Currency::PLN,
     }
   }
+
+  /// Runs every scenario `fn(impl Database)` of the calling module against each backend,
+  /// the test of the scenario `name` for JSON backend is `json::name`.
+  /// New backend gets all scenarios by adding its mock database to the list below.
+  #[cfg(test)]
+  macro_rules! backend_tests {
+    ($($scenario:ident),+ $(,)?) => {
+      $crate::database::tests::backend_tests!(@backend json, $crate::database::json::tests::get_mock_db; $($scenario),+);
+      $crate::database::tests::backend_tests!(@backend sqlite, $crate::database::sqlite::tests::get_mock_db; $($scenario),+);
+      $crate::database::tests::backend_tests!(@backend memory, $crate::database::memory::tests::get_mock_db; $($scenario),+);
+    };
+    (@backend $backend:ident, $get_mock_db:path; $($scenario:ident),+) => {
+      mod $backend {
+        $(
+          #[test]
+          fn $scenario() {
+            super::$scenario($get_mock_db());
+          }
+        )+
+      }
+    };
+  }
+
+  #[cfg(test)]
+  pub(crate) use backend_tests;
 }
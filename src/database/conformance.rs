@@ -0,0 +1,322 @@
+//! Contract of the `Database` trait which every backend has to fulfil
+
+use crate::database::{Database, ErrorKind, LoginAttempts, TransactionKind};
+use crate::database::tests::{backend_tests, get_mock_client};
+use crate::money::Money;
+use crate::currency::{Currency, ExchangeRates};
+use crate::card_number::{CardNumberGenerator, CardNumberGeneratorError};
+use crate::Client;
+
+backend_tests!(
+  save_and_get_client,
+  reject_duplicate_client,
+  report_missing_client,
+  count_clients,
+  add_funds,
+  reject_invalid_amount,
+  reject_balance_overflow,
+  transfer_funds,
+  reject_insufficient_funds,
+  reject_transfer_without_exchange_rate,
+  reject_transfer_to_own_card,
+  remove_client,
+  never_reissue_closed_card_number,
+  update_pin,
+  count_failed_logins,
+  import_all_or_nothing,
+);
+
+fn get_client(card_number: &str, balance: i64) -> Client {
+  Client {
+    card_number: card_number.to_owned(),
+    balance: Money::from_minor_units(balance),
+    ..get_mock_client()
+  }
+}
+
+fn balance_of(db: &impl Database, card_number: &str) -> Money {
+  db.get_client(card_number).unwrap().balance
+}
+
+fn save_and_get_client(mut db: impl Database) {
+  let client = get_client("4000000000000000", 1234);
+
+  assert!(!db.has_client(&client.card_number).unwrap());
+
+  db.save_new_client(client.clone()).unwrap();
+
+  assert!(db.has_client(&client.card_number).unwrap());
+  assert_eq!(db.get_client(&client.card_number).unwrap(), client);
+  assert!(db.get_history(&client.card_number).unwrap().is_empty());
+}
+
+fn reject_duplicate_client(mut db: impl Database) {
+  let client = get_client("4000000000000000", 100);
+  db.save_new_client(client.clone()).unwrap();
+
+  let duplicate = get_client("4000000000000000", 0);
+  let report = db.save_new_client(duplicate).unwrap_err();
+
+  assert_eq!(ErrorKind::of(&report), ErrorKind::ClientAlreadyExists);
+  assert_eq!(db.get_clients_count().unwrap(), 1);
+  assert_eq!(db.get_client(&client.card_number).unwrap(), client);
+}
+
+fn report_missing_client(mut db: impl Database) {
+  let client = get_client("4000000000000000", 100);
+  db.save_new_client(client.clone()).unwrap();
+
+  let missing = "4000000000000001";
+  let one = Money::from_minor_units(1);
+  let rates = ExchangeRates::default();
+
+  assert!(!db.has_client(missing).unwrap());
+  assert!(db.get_history(missing).unwrap().is_empty());
+
+  let reports = [
+    db.get_client(missing).map(|_| ()),
+    db.remove_client(missing).map(|_| ()),
+    db.add_funds(one, missing),
+    db.update_pin(missing, "1234"),
+    db.transfer_funds(one, missing, &client.card_number, &rates),
+    db.transfer_funds(one, &client.card_number, missing, &rates),
+    db.register_failed_login(missing, 3).map(|_| ()),
+    db.reset_login_attempts(missing),
+  ];
+
+  for (case, result) in reports.into_iter().enumerate() {
+    let report = result.unwrap_err();
+    assert_eq!(ErrorKind::of(&report), ErrorKind::ClientNotFound, "case: {case}, {report:?}");
+  }
+
+  assert_eq!(db.get_client(&client.card_number).unwrap(), client);
+  assert!(db.get_history(&client.card_number).unwrap().is_empty());
+}
+
+fn count_clients(mut db: impl Database) {
+  assert_eq!(db.get_clients_count().unwrap(), 0);
+
+  for (count, card_number) in ["4000000000000000", "4000000000000001", "4000000000000002"].iter().enumerate() {
+    db.save_new_client(get_client(card_number, 0)).unwrap();
+    assert_eq!(db.get_clients_count().unwrap(), count as u32 + 1);
+  }
+
+  db.remove_client("4000000000000001").unwrap();
+  assert_eq!(db.get_clients_count().unwrap(), 2);
+}
+
+fn add_funds(mut db: impl Database) {
+  let card_number = "4000000000000000";
+  db.save_new_client(get_client(card_number, 100)).unwrap();
+
+  db.add_funds(Money::from_minor_units(250), card_number).unwrap();
+
+  assert_eq!(balance_of(&db, card_number), Money::from_minor_units(350));
+
+  let history = db.get_history(card_number).unwrap();
+  assert_eq!(history.len(), 1);
+  assert_eq!(history[0].kind, TransactionKind::Deposit);
+  assert_eq!(history[0].amount, Money::from_minor_units(250));
+  assert_eq!(history[0].balance, Money::from_minor_units(350));
+  assert_eq!(history[0].counterparty, None);
+}
+
+fn reject_invalid_amount(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = "4000000000000001";
+  db.save_new_client(get_client(sender, 100)).unwrap();
+  db.save_new_client(get_client(receiver, 100)).unwrap();
+
+  for funds in [Money::ZERO, Money::from_minor_units(-1)] {
+    let report = db.add_funds(funds, sender).unwrap_err();
+    assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount, "{report:?}");
+
+    let report = db.transfer_funds(funds, sender, receiver, &ExchangeRates::default()).unwrap_err();
+    assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount, "{report:?}");
+  }
+
+  assert_eq!(balance_of(&db, sender), Money::from_minor_units(100));
+  assert_eq!(balance_of(&db, receiver), Money::from_minor_units(100));
+  assert!(db.get_history(sender).unwrap().is_empty());
+}
+
+fn reject_balance_overflow(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = "4000000000000001";
+  db.save_new_client(get_client(sender, 100)).unwrap();
+  db.save_new_client(get_client(receiver, i64::MAX - 1)).unwrap();
+
+  let report = db.add_funds(Money::from_minor_units(2), receiver).unwrap_err();
+  assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount, "{report:?}");
+
+  let report = db.transfer_funds(Money::from_minor_units(2), sender, receiver, &ExchangeRates::default()).unwrap_err();
+  assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount, "{report:?}");
+
+  assert_eq!(balance_of(&db, sender), Money::from_minor_units(100));
+  assert_eq!(balance_of(&db, receiver), Money::from_minor_units(i64::MAX - 1));
+  assert!(db.get_history(sender).unwrap().is_empty());
+  assert!(db.get_history(receiver).unwrap().is_empty());
+}
+
+fn transfer_funds(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = "4000000000000001";
+  db.save_new_client(get_client(sender, 500)).unwrap();
+  db.save_new_client(get_client(receiver, 100)).unwrap();
+
+  db.transfer_funds(Money::from_minor_units(500), sender, receiver, &ExchangeRates::default()).unwrap();
+
+  assert_eq!(balance_of(&db, sender), Money::ZERO);
+  assert_eq!(balance_of(&db, receiver), Money::from_minor_units(600));
+
+  let sender_history = db.get_history(sender).unwrap();
+  assert_eq!(sender_history.len(), 1);
+  assert_eq!(sender_history[0].kind, TransactionKind::TransferOut);
+  assert_eq!(sender_history[0].balance, Money::ZERO);
+  assert_eq!(sender_history[0].counterparty.as_deref(), Some(receiver));
+  assert_eq!(sender_history[0].exchange_rate, None);
+
+  let receiver_history = db.get_history(receiver).unwrap();
+  assert_eq!(receiver_history.len(), 1);
+  assert_eq!(receiver_history[0].kind, TransactionKind::TransferIn);
+  assert_eq!(receiver_history[0].amount, Money::from_minor_units(500));
+  assert_eq!(receiver_history[0].counterparty.as_deref(), Some(sender));
+  assert!(receiver_history[0].id > sender_history[0].id);
+}
+
+fn reject_insufficient_funds(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = "4000000000000001";
+  db.save_new_client(get_client(sender, 100)).unwrap();
+  db.save_new_client(get_client(receiver, 0)).unwrap();
+
+  let report = db.transfer_funds(Money::from_minor_units(101), sender, receiver, &ExchangeRates::default()).unwrap_err();
+
+  assert_eq!(ErrorKind::of(&report), ErrorKind::InsufficientFunds, "{report:?}");
+  assert_eq!(balance_of(&db, sender), Money::from_minor_units(100));
+  assert_eq!(balance_of(&db, receiver), Money::ZERO);
+  assert!(db.get_history(sender).unwrap().is_empty());
+  assert!(db.get_history(receiver).unwrap().is_empty());
+}
+
+fn reject_transfer_without_exchange_rate(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = Client {
+    currency: "EUR".parse::<Currency>().unwrap(),
+    ..get_client("4000000000000001", 0)
+  };
+  db.save_new_client(get_client(sender, 100)).unwrap();
+  db.save_new_client(receiver.clone()).unwrap();
+
+  let report = db.transfer_funds(Money::from_minor_units(50), sender, &receiver.card_number, &ExchangeRates::default())
+    .unwrap_err();
+
+  assert_eq!(ErrorKind::of(&report), ErrorKind::CurrencyConversionFailed, "{report:?}");
+  assert_eq!(balance_of(&db, sender), Money::from_minor_units(100));
+  assert_eq!(balance_of(&db, &receiver.card_number), Money::ZERO);
+}
+
+fn reject_transfer_to_own_card(mut db: impl Database) {
+  let card_number = "4000000000000000";
+  db.save_new_client(get_client(card_number, 500)).unwrap();
+
+  let report = db.transfer_funds(Money::from_minor_units(100), card_number, card_number, &ExchangeRates::default())
+    .unwrap_err();
+
+  assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidArgument, "{report:?}");
+  assert_eq!(balance_of(&db, card_number), Money::from_minor_units(500));
+  assert!(db.get_history(card_number).unwrap().is_empty());
+}
+
+fn remove_client(mut db: impl Database) {
+  let client = get_client("4000000000000000", 700);
+  db.save_new_client(client.clone()).unwrap();
+  db.register_failed_login(&client.card_number, 1).unwrap();
+
+  assert_eq!(db.remove_client(&client.card_number).unwrap(), client);
+
+  assert!(!db.has_client(&client.card_number).unwrap());
+  assert_eq!(db.get_clients_count().unwrap(), 0);
+
+  let history = db.get_history(&client.card_number).unwrap();
+  assert_eq!(history.len(), 1);
+  assert_eq!(history[0].kind, TransactionKind::AccountClosed);
+  assert_eq!(history[0].amount, Money::from_minor_units(700));
+  assert_eq!(history[0].balance, Money::ZERO);
+
+  db.save_new_client(get_client(&client.card_number, 0)).unwrap();
+  assert_eq!(db.get_login_attempts(&client.card_number).unwrap(), LoginAttempts::default());
+}
+
+fn never_reissue_closed_card_number(mut db: impl Database) {
+  use rand::SeedableRng;
+  use rand::rngs::SmallRng;
+
+  // prefix leaves room only for the check digit, so the range has a single card number
+  let card_numbers = CardNumberGenerator::from_ranges(&[String::from("40000000000:12")]).unwrap();
+  let mut rng = SmallRng::seed_from_u64(0);
+
+  let card_number = card_numbers.generate(&db, &mut rng).unwrap();
+  db.save_new_client(get_client(&card_number, 0)).unwrap();
+  db.add_funds(Money::from_minor_units(100), &card_number).unwrap();
+  db.remove_client(&card_number).unwrap();
+
+  assert!(!db.has_client(&card_number).unwrap());
+  assert!(db.is_card_number_used(&card_number).unwrap());
+  assert!(!db.is_card_number_used("4000000000000002").unwrap());
+
+  let report = card_numbers.generate(&db, &mut rng).unwrap_err();
+  assert!(matches!(report.current_context(), CardNumberGeneratorError::NoFreeCardNumber), "{report:?}");
+}
+
+fn update_pin(mut db: impl Database) {
+  let client = get_client("4000000000000000", 0);
+  db.save_new_client(client.clone()).unwrap();
+
+  db.update_pin(&client.card_number, "$argon2id$hash").unwrap();
+
+  let updated = db.get_client(&client.card_number).unwrap();
+  assert_eq!(updated.pin, "$argon2id$hash");
+  assert_eq!(updated.balance, client.balance);
+}
+
+fn count_failed_logins(mut db: impl Database) {
+  let card_number = "4000000000000000";
+  db.save_new_client(get_client(card_number, 0)).unwrap();
+
+  assert_eq!(db.get_login_attempts(card_number).unwrap(), LoginAttempts::default());
+
+  let attempts = db.register_failed_login(card_number, 2).unwrap();
+  assert_eq!(attempts, LoginAttempts { failed: 1, locked: false });
+
+  let attempts = db.register_failed_login(card_number, 2).unwrap();
+  assert_eq!(attempts, LoginAttempts { failed: 2, locked: true });
+
+  let attempts = db.register_failed_login(card_number, 5).unwrap();
+  assert_eq!(attempts, LoginAttempts { failed: 3, locked: true });
+  assert_eq!(db.get_login_attempts(card_number).unwrap(), attempts);
+
+  db.reset_login_attempts(card_number).unwrap();
+  assert_eq!(db.get_login_attempts(card_number).unwrap(), LoginAttempts::default());
+}
+
+fn import_all_or_nothing(mut db: impl Database) {
+  let sender = "4000000000000000";
+  let receiver = "4000000000000001";
+  db.save_new_client(get_client(sender, 300)).unwrap();
+  db.save_new_client(get_client(receiver, 0)).unwrap();
+  db.transfer_funds(Money::from_minor_units(100), sender, receiver, &ExchangeRates::default()).unwrap();
+  db.register_failed_login(receiver, 1).unwrap();
+
+  let mut snapshot = db.export().unwrap();
+  assert_eq!(snapshot.clients.len(), 2);
+  assert_eq!(snapshot.transactions.len(), 2);
+  assert!(snapshot.login_attempts[receiver].locked);
+
+  snapshot.clients.insert(0, get_client("4000000000000002", 0));
+
+  let report = db.import(snapshot.clone()).unwrap_err();
+  assert_eq!(ErrorKind::of(&report), ErrorKind::ClientAlreadyExists);
+  assert!(!db.has_client("4000000000000002").unwrap());
+  assert_eq!(db.export().unwrap().transactions.len(), 2);
+}
@@ -1,12 +1,18 @@
 use crate::Database;
 use crate::Client;
+use crate::database::{ErrorKind, LoginAttempts, Snapshot, Transaction, TransactionKind};
+use crate::money::Money;
+use crate::currency::{Currency, ExchangeRates};
 use crate::{DatabaseError, DatabaseResult};
 
 use serde::{Deserialize, Serialize};
 use error_stack::{Context, Result, IntoReport, Report, ResultExt};
 
 use std::fmt;
+use std::cell::{Ref, RefCell};
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug)]
 pub enum JsonDatabaseError {
@@ -16,6 +22,13 @@ pub enum JsonDatabaseError {
   ReadingDatabaseFile,
   ClientNotFound,
   InsufficientFunds,
+  InvalidAmount,
+  BalanceOverflow,
+  CurrencyConversionFailed,
+  TransferToSameCard,
+  LockingFailed,
+  LockTimeout,
+  UnsupportedVersion(u64),
   ClientAlreadyInDatabase(String)
 }
 
@@ -28,6 +41,16 @@ impl fmt::Display for JsonDatabaseError {
       JsonDatabaseError::SavingDatabaseFile => write!(f, "saving database file failed"),
       JsonDatabaseError::ClientNotFound => write!(f, "client not found in database"),
       JsonDatabaseError::InsufficientFunds => write!(f, "operation failed due to insufficient funds"),
+      JsonDatabaseError::InvalidAmount => write!(f, "amount must be positive"),
+      JsonDatabaseError::BalanceOverflow => write!(f, "balance would exceed maximal value"),
+      JsonDatabaseError::CurrencyConversionFailed => write!(f, "currency conversion failed"),
+      JsonDatabaseError::TransferToSameCard => write!(f, "sender and receiver card numbers are the same"),
+      JsonDatabaseError::LockingFailed => write!(f, "locking database file failed"),
+      JsonDatabaseError::LockTimeout => write!(f, "database file is locked by another process, try again later"),
+      JsonDatabaseError::UnsupportedVersion(version) => write!(
+        f,
+        "database file format version {version} is newer than supported version {FORMAT_VERSION}"
+      ),
       JsonDatabaseError::ClientAlreadyInDatabase(card_number) => write!(f, "client with {card_number} already exists in database")
     }
   }
@@ -35,91 +58,278 @@ impl fmt::Display for JsonDatabaseError {
 
 impl Context for JsonDatabaseError {}
 
+impl JsonDatabaseError {
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      JsonDatabaseError::ClientNotFound => ErrorKind::ClientNotFound,
+      JsonDatabaseError::ClientAlreadyInDatabase(_) => ErrorKind::ClientAlreadyExists,
+      JsonDatabaseError::InsufficientFunds => ErrorKind::InsufficientFunds,
+      JsonDatabaseError::InvalidAmount | JsonDatabaseError::BalanceOverflow => ErrorKind::InvalidAmount,
+      JsonDatabaseError::CurrencyConversionFailed => ErrorKind::CurrencyConversionFailed,
+      JsonDatabaseError::TransferToSameCard => ErrorKind::InvalidArgument,
+      _ => ErrorKind::Other,
+    }
+  }
+}
+
 pub type JsonDataBaseResult<T> = Result<T, JsonDatabaseError>;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct DatabaseData {
+  pub version: u32,
   pub clients: BTreeMap<String, Client>,
+  #[serde(default)]
+  pub transactions: Vec<Transaction>,
+  #[serde(default)]
+  pub login_attempts: BTreeMap<String, LoginAttempts>,
 }
 
 impl DatabaseData {
   pub fn new() -> Self {
-    DatabaseData { clients: BTreeMap::new() }
+    DatabaseData {
+      version: FORMAT_VERSION,
+      clients: BTreeMap::new(),
+      transactions: Vec::new(),
+      login_attempts: BTreeMap::new(),
+    }
+  }
+
+  /// Parses content of data.json file of any supported format version
+  pub fn from_json(json: &str) -> JsonDataBaseResult<Self> {
+    json_impl::data_from_json(json)
+  }
+
+  fn record_transaction(&mut self, mut transaction: Transaction) {
+    transaction.id = match self.transactions.last() {
+      None => 1,
+      Some(last) => last.id + 1,
+    };
+
+    self.transactions.push(transaction);
   }
 }
 
 pub struct JsonDb {
   read_json_file: Box<dyn Fn() -> JsonDataBaseResult<String>>,
   write_json_to_file: Box<dyn Fn(&str) -> JsonDataBaseResult<()>>,
+  read_backup_file: Box<dyn Fn(u32) -> JsonDataBaseResult<String>>,
+  lock_file: Box<dyn Fn(LockKind) -> JsonDataBaseResult<FileLock>>,
+  file_stamp: Box<dyn Fn() -> Option<FileStamp>>,
+  cache: RefCell<Option<Cache>>,
+}
+
+/// Modification time, size and identity of the database file, changes when other process saves the file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStamp {
+  modified: SystemTime,
+  len: u64,
+  /// Device and inode, every save renames a new file over the database file, so they change
+  /// also when the new content has the same length and mtime is too coarse to differ
+  file_id: (u64, u64),
+}
+
+/// Parsed database file, `stamp` is taken before reading so a concurrent write makes it stale
+struct Cache {
+  stamp: Option<FileStamp>,
+  data: DatabaseData,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockKind {
+  Shared,
+  Exclusive,
+}
+
+/// Advisory lock of the database, released when dropped, mock databases lock no file
+pub struct FileLock {
+  file: Option<std::fs::File>,
+  kind: LockKind,
+}
+
+impl Drop for FileLock {
+  fn drop(&mut self) {
+    if let Some(file) = &self.file {
+      let _ = file.unlock();
+    }
+  }
+}
+
+/// How long to wait for other process to release the database file
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version of the database file format written by this build, see `upgrade_impl::UPGRADES`
+pub const FORMAT_VERSION: u32 = upgrade_impl::UPGRADES.len() as u32;
+
+/// Number of previous database file versions kept as `<file>.bak.1` (newest) to `<file>.bak.N`
+pub const BACKUP_GENERATIONS: u32 = 3;
+
+/// Previous versions of the database file at `path`, newest first, whether they exist or not
+pub fn backup_paths(path: &Path) -> Vec<PathBuf> {
+  (1..=BACKUP_GENERATIONS)
+    .map(|generation| fs_impl::backup_path(path, generation))
+    .collect()
+}
+
+fn is_file_missing(report: &Report<JsonDatabaseError>) -> bool {
+  report
+    .downcast_ref::<std::io::Error>()
+    .is_some_and(|error| error.kind() == std::io::ErrorKind::NotFound)
 }
 
 impl JsonDb {
-  pub fn new() -> Self {
+  pub fn new(path: &Path) -> Self {
+    let read_path = path.to_owned();
+    let write_path = path.to_owned();
+    let backup_path = path.to_owned();
+    let lock_path = path.to_owned();
+    let stamp_path = path.to_owned();
+
     let db = JsonDb {
-      read_json_file: Box::new(fs_impl::read_json_file),
-      write_json_to_file: Box::new(fs_impl::write_json_to_file),
+      read_json_file: Box::new(move || fs_impl::read_json_file(&read_path)),
+      write_json_to_file: Box::new(move |json| fs_impl::write_json_to_file(&write_path, json)),
+      read_backup_file: Box::new(move |generation| {
+        fs_impl::read_json_file(&fs_impl::backup_path(&backup_path, generation))
+      }),
+      lock_file: Box::new(move |kind| fs_impl::lock_file(&lock_path, kind, LOCK_TIMEOUT)),
+      file_stamp: Box::new(move || fs_impl::file_stamp(&stamp_path)),
+      cache: RefCell::new(None),
     };
 
-    if let Err(error) = db.read_data() {
-      println!("\n failed to read json database, error: {:?}", error);
+    if let Err(error) = db.lock(LockKind::Exclusive).and_then(|lock| db.data(&lock).map(|_| ())) {
+      eprintln!("\n failed to read json database, error: {:?}", error);
       panic!("JsonDb::new() failed");
     }
 
     db
   }
 
-  fn read_data(&self) -> JsonDataBaseResult<DatabaseData> {
+  /// Held for the whole read-modify-write cycle, so other processes do not lose the update
+  fn lock(&self, kind: LockKind) -> JsonDataBaseResult<FileLock> {
+    let lock_file = self.lock_file.as_ref();
+
+    lock_file(kind)
+  }
+
+  /// Cached data, reloaded only when the database file was changed since the last read or save
+  fn data(&self, lock: &FileLock) -> JsonDataBaseResult<Ref<'_, DatabaseData>> {
+    let file_stamp = self.file_stamp.as_ref();
+
+    let stamp = file_stamp();
+    let is_fresh = match (self.cache.borrow().as_ref(), stamp) {
+      (Some(cache), Some(stamp)) => cache.stamp == Some(stamp),
+      _ => false,
+    };
+
+    if !is_fresh {
+      let data = self.read_data(lock)?;
+      self.cache.replace(Some(Cache { stamp, data }));
+    }
+
+    Ok(Ref::map(self.cache.borrow(), |cache| {
+      &cache.as_ref().expect("cache filled above").data
+    }))
+  }
+
+  /// Moves the data out of the cache to change it, `save_data` caches the changed data,
+  /// so failed change leaves no cache and the file is read again
+  fn take_data(&self, lock: &FileLock) -> JsonDataBaseResult<DatabaseData> {
+    drop(self.data(lock)?);
+
+    let cache = self.cache.take().expect("cache filled by data()");
+
+    Ok(cache.data)
+  }
+
+  fn read_data(&self, lock: &FileLock) -> JsonDataBaseResult<DatabaseData> {
     let read_json_file = self.read_json_file.as_ref();
 
     let data = match read_json_file() {
-      Err(e) => {
-        println!("try to create new database file because: {:?}", e);
+      // file lost while backups are left is recovered, only the first start gets empty database
+      Err(report) if is_file_missing(&report) && !self.has_backups() => {
+        eprintln!("creating new database file, because it does not exist: {:?}", report);
 
         let data = DatabaseData::new();
 
-        self.save_data(&data)
+        self.write_repaired_data(&data, lock)
           .attach_printable("creating new database file failed")?;
 
         data
       },
-      Ok(str) => {
-        json_impl::data_from_json(&str)?
-      }
+      Err(report) if is_file_missing(&report) => self.recover_from_backup(report, lock)?,
+      // unreadable file may still hold data, it must not be replaced
+      Err(report) => return Err(report),
+      Ok(str) => match json_impl::data_from_json(&str) {
+        Ok(data) => data,
+        // file written by a newer build is not broken, replacing it with backup would lose data
+        Err(report) if matches!(report.current_context(), JsonDatabaseError::UnsupportedVersion(_)) => {
+          return Err(report);
+        },
+        Err(report) => self.recover_from_backup(report, lock)?,
+      },
     };
 
     Ok(data)
   }
 
-  fn save_data(&self, data: &DatabaseData) -> JsonDataBaseResult<()> {
-    let write_json_to_file = self.write_json_to_file.as_ref();
+  fn has_backups(&self) -> bool {
+    let read_backup_file = self.read_backup_file.as_ref();
 
-    let json = json_impl::data_to_json_str(data)?;
+    (1..=BACKUP_GENERATIONS).any(|generation| match read_backup_file(generation) {
+      Err(report) => !is_file_missing(&report),
+      Ok(_) => true,
+    })
+  }
 
-    write_json_to_file(&json)
-      .attach_printable_lazy(|| {
-        format!("failed to save data")
-      })?;
+  /// Restores the newest backup which can be parsed, it replaces the broken database file
+  fn recover_from_backup(&self, report: Report<JsonDatabaseError>, lock: &FileLock) -> JsonDataBaseResult<DatabaseData> {
+    let read_backup_file = self.read_backup_file.as_ref();
 
-    Ok(())
-  }
+    for generation in 1..=BACKUP_GENERATIONS {
+      let data = read_backup_file(generation)
+        .and_then(|json| json_impl::data_from_json(&json));
 
-  fn save_clients(&mut self, clients: &[Client]) -> DatabaseResult<()> {
-    let mut data = self.read_data()
-      .attach_printable_lazy(|| {
-        format!("failed to read data from json, before clients save")
-      })
-      .change_context(DatabaseError::JSON)?;
+      if let Ok(data) = data {
+        eprintln!("database file is broken, recovering from backup {}: {:?}", generation, report);
 
+        self.write_repaired_data(&data, lock)
+          .attach_printable("saving recovered database file failed")?;
 
-    for client in clients {
-      data.clients.insert(client.card_number.clone(), client.clone());
+        return Ok(data);
+      }
     }
 
-    self.save_data(&data)
+    Err(report)
+      .attach_printable("no valid backup found")
+  }
+
+  /// Replaces missing or broken database file only under exclusive lock, so concurrent readers
+  /// never write the file, they use the data and leave the write to the next change
+  fn write_repaired_data(&self, data: &DatabaseData, lock: &FileLock) -> JsonDataBaseResult<()> {
+    match lock.kind {
+      LockKind::Exclusive => self.write_data(data),
+      LockKind::Shared => Ok(()),
+    }
+  }
+
+  /// Writes the data and keeps it as cache, must be called under exclusive lock
+  fn save_data(&self, data: DatabaseData) -> JsonDataBaseResult<()> {
+    let file_stamp = self.file_stamp.as_ref();
+
+    self.write_data(&data)?;
+    self.cache.replace(Some(Cache { stamp: file_stamp(), data }));
+
+    Ok(())
+  }
+
+  fn write_data(&self, data: &DatabaseData) -> JsonDataBaseResult<()> {
+    let write_json_to_file = self.write_json_to_file.as_ref();
+
+    let json = json_impl::data_to_json_str(data)?;
+
+    write_json_to_file(&json)
       .attach_printable_lazy(|| {
-        format!("failed to save {} clients: {:?}", clients.len(), clients)
-      })
-      .change_context(DatabaseError::JSON)?;
+        format!("failed to save data")
+      })?;
 
     Ok(())
   }
@@ -131,7 +341,10 @@ impl Database for JsonDb {
   }
 
   fn save_new_client(&mut self, client: Client) -> DatabaseResult<()> {
-    let mut data = self.read_data()
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    let mut data = self.take_data(&lock)
       .attach_printable_lazy(|| {
         format!("failed to read data from json, before new client save")
       })
@@ -150,7 +363,7 @@ impl Database for JsonDb {
       client.clone()
     );
 
-    self.save_data(&data)
+    self.save_data(data)
       .attach_printable_lazy(|| {
         format!("failed insert new client, client: {:?}", client)
       })
@@ -160,14 +373,20 @@ impl Database for JsonDb {
   }
 
   fn has_client(&self, card_number: &str) -> DatabaseResult<bool> {
-    let data = self.read_data()
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
       .change_context(DatabaseError::JSON)?;
 
     Ok(data.clients.contains_key(card_number))
   }
 
   fn get_client(&self, card_number: &str) -> DatabaseResult<Client> {
-    let data = self.read_data()
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
       .change_context(DatabaseError::JSON)?;
 
     match data.clients.get(card_number) {
@@ -181,81 +400,278 @@ impl Database for JsonDb {
   }
 
   fn remove_client(&mut self, card_number: &str) -> DatabaseResult<Client> {
-    let mut data = self.read_data()
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    let mut data = self.take_data(&lock)
       .change_context(DatabaseError::JSON)?;
 
     let client = match data.clients.remove(card_number) {
-      None => Err(Report::new(JsonDatabaseError::ClientNotFound))
+      None => return Err(Report::new(JsonDatabaseError::ClientNotFound))
         .attach_printable_lazy(|| {
           format!("client with card_number: {} is not present in database", card_number)
         })
         .change_context(DatabaseError::JSON),
-      Some(client) => Ok(client)
+      Some(client) => client
     };
 
-    self.save_data(&data)
+    data.login_attempts.remove(card_number);
+
+    data.record_transaction(
+      Transaction::new(card_number, TransactionKind::AccountClosed, client.balance, Money::ZERO)
+    );
+
+    self.save_data(data)
+      .attach_printable("failed to remove client because save_data error")
+      .change_context(DatabaseError::JSON)?;
+
+    Ok(client)
+  }
+
+  fn add_funds(&mut self, funds: Money, card_number: &str) -> DatabaseResult<()> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    json_impl::check_funds(funds)?;
+
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before adding funds")
+      .change_context(DatabaseError::JSON)?;
+
+    let client = json_impl::get_client_mut(&mut data, card_number)?;
+    let balance = json_impl::add_to_balance(client, funds)?;
+
+    data.record_transaction(
+      Transaction::new(card_number, TransactionKind::Deposit, funds, balance)
+    );
+
+    self.save_data(data)
       .attach_printable_lazy(|| {
-        format!("failed to remove client because save_data error")
+        format!("failed to save funds: {} for card_number: {}", funds, card_number)
       })
       .change_context(DatabaseError::JSON)?;
 
-    client
+    Ok(())
   }
 
-  fn add_funds(&mut self, funds: u32, card_number: &str) -> DatabaseResult<()> {
-    let mut client = self.get_client(card_number)?;
+  fn update_pin(&mut self, card_number: &str, pin: &str) -> DatabaseResult<()> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
 
-    client.balance += funds as i32;
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before PIN update")
+      .change_context(DatabaseError::JSON)?;
 
-    self.save_clients(&[client])?;
+    json_impl::get_client_mut(&mut data, card_number)?.pin = pin.to_owned();
+
+    self.save_data(data)
+      .attach_printable_lazy(|| {
+        format!("failed to save new PIN for card_number: {}", card_number)
+      })
+      .change_context(DatabaseError::JSON)?;
 
     Ok(())
   }
 
-  fn transfer_funds(&mut self, funds: u32, sender_card_number: &str, receiver_card_number: &str) -> DatabaseResult<()> {
-    let mut sender_client = self.get_client(sender_card_number)
+  fn transfer_funds(
+    &mut self,
+    funds: Money,
+    sender_card_number: &str,
+    receiver_card_number: &str,
+    rates: &ExchangeRates
+  ) -> DatabaseResult<()> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    json_impl::check_funds(funds)?;
+    json_impl::check_different_cards(sender_card_number, receiver_card_number)?;
+
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before transfer")
+      .change_context(DatabaseError::JSON)?;
+
+    let sender_client = json_impl::get_client_mut(&mut data, sender_card_number)
       .attach_printable_lazy(|| {
         format!("sender client not found, sender_card_number: {}", sender_card_number)
       })?;
+    let sender_original_balance = sender_client.balance;
+    let sender_currency = sender_client.currency;
 
-    let mut receiver_client = self.get_client(receiver_card_number)
+    let receiver_currency = json_impl::get_client_mut(&mut data, receiver_card_number)
       .attach_printable_lazy(|| {
         format!("receiver client not found, receiver_card_number: {}", receiver_card_number)
-      })?;
+      })?
+      .currency;
 
-    let sender_original_balance = sender_client.balance;
-    sender_client.balance -= funds as i32;
+    let (received_funds, exchange_rate) = rates.convert(funds, sender_currency, receiver_currency)
+      .change_context(JsonDatabaseError::CurrencyConversionFailed)
+      .change_context(DatabaseError::JSON)?;
 
-    if sender_client.balance < 0 {
-      return Err(Report::new(JsonDatabaseError::InsufficientFunds))
+    let sender_balance = match sender_original_balance.checked_sub(funds) {
+      Some(balance) if !balance.is_negative() => balance,
+      _ => return Err(Report::new(JsonDatabaseError::InsufficientFunds))
         .attach_printable_lazy(|| {
           format!(
-            "sender's balance before transfer: {}, after transfer: {}",
+            "sender's balance before transfer: {}, transferred funds: {}",
             sender_original_balance,
-            sender_client.balance
+            funds
           )
         })
-        .change_context(DatabaseError::JSON)
-    }
+        .change_context(DatabaseError::JSON),
+    };
+
+    json_impl::get_client_mut(&mut data, sender_card_number)?.balance = sender_balance;
+
+    let receiver_client = json_impl::get_client_mut(&mut data, receiver_card_number)?;
+    let receiver_balance = json_impl::add_to_balance(receiver_client, received_funds)?;
+
+    data.record_transaction(
+      Transaction::new(sender_card_number, TransactionKind::TransferOut, funds, sender_balance)
+        .with_counterparty(receiver_card_number, exchange_rate)
+    );
+    data.record_transaction(
+      Transaction::new(receiver_card_number, TransactionKind::TransferIn, received_funds, receiver_balance)
+        .with_counterparty(sender_card_number, exchange_rate)
+    );
+
+    self.save_data(data)
+      .attach_printable("failed to save clients data in database")
+      .change_context(DatabaseError::JSON)?;
+
+    Ok(())
+  }
+
+  fn get_login_attempts(&self, card_number: &str) -> DatabaseResult<LoginAttempts> {
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
+      .change_context(DatabaseError::JSON)?;
+
+    Ok(data.login_attempts.get(card_number).copied().unwrap_or_default())
+  }
 
-    receiver_client.balance += funds as i32;
+  fn register_failed_login(&mut self, card_number: &str, max_attempts: u32) -> DatabaseResult<LoginAttempts> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before failed login save")
+      .change_context(DatabaseError::JSON)?;
+
+    json_impl::get_client_mut(&mut data, card_number)?;
 
-    let clients = [sender_client, receiver_client];
+    let attempts = data.login_attempts
+      .entry(card_number.to_owned())
+      .or_default();
 
-    self.save_clients(&clients)
+    attempts.failed += 1;
+    attempts.locked = attempts.locked || attempts.failed >= max_attempts;
+
+    let attempts = *attempts;
+
+    self.save_data(data)
       .attach_printable_lazy(|| {
-        format!("failed to save clients data in database")
-      })?;
+        format!("failed to save failed login of card_number: {}", card_number)
+      })
+      .change_context(DatabaseError::JSON)?;
+
+    Ok(attempts)
+  }
+
+  fn reset_login_attempts(&mut self, card_number: &str) -> DatabaseResult<()> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before login attempts reset")
+      .change_context(DatabaseError::JSON)?;
+
+    json_impl::get_client_mut(&mut data, card_number)?;
+
+    if data.login_attempts.remove(card_number).is_none() {
+      return Ok(());
+    }
+
+    self.save_data(data)
+      .attach_printable_lazy(|| {
+        format!("failed to reset login attempts of card_number: {}", card_number)
+      })
+      .change_context(DatabaseError::JSON)?;
 
     Ok(())
   }
 
   fn get_clients_count(&self) -> DatabaseResult<u32> {
-    let data = self.read_data()
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
       .change_context(DatabaseError::JSON)?;
 
     Ok(data.clients.len() as u32)
   }
+
+  fn get_history(&self, card_number: &str) -> DatabaseResult<Vec<Transaction>> {
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
+      .change_context(DatabaseError::JSON)?;
+
+    let history = data.transactions
+      .iter()
+      .filter(|transaction| transaction.card_number == card_number)
+      .cloned()
+      .collect();
+
+    Ok(history)
+  }
+
+  fn export(&self) -> DatabaseResult<Snapshot> {
+    let lock = self.lock(LockKind::Shared)
+      .change_context(DatabaseError::JSON)?;
+
+    let data = self.data(&lock)
+      .change_context(DatabaseError::JSON)?;
+
+    Ok(Snapshot {
+      clients: data.clients.values().cloned().collect(),
+      login_attempts: data.login_attempts.clone(),
+      transactions: data.transactions.clone(),
+    })
+  }
+
+  fn import(&mut self, snapshot: Snapshot) -> DatabaseResult<()> {
+    let lock = self.lock(LockKind::Exclusive)
+      .change_context(DatabaseError::JSON)?;
+
+    let mut data = self.take_data(&lock)
+      .attach_printable("failed to read data from json, before import")
+      .change_context(DatabaseError::JSON)?;
+
+    for client in snapshot.clients {
+      if data.clients.contains_key(&client.card_number) {
+        return Err(
+          Report::new(
+            JsonDatabaseError::ClientAlreadyInDatabase(client.card_number)
+          )
+        ).change_context(DatabaseError::JSON)
+      }
+
+      data.clients.insert(client.card_number.clone(), client);
+    }
+
+    data.login_attempts.extend(snapshot.login_attempts);
+
+    for transaction in snapshot.transactions {
+      data.record_transaction(transaction);
+    }
+
+    self.save_data(data)
+      .attach_printable("failed to save imported data")
+      .change_context(DatabaseError::JSON)
+  }
 }
 
 mod json_impl {
@@ -270,18 +686,175 @@ mod json_impl {
     .change_context(JsonDatabaseError::Serialization)
   }
 
+  /// Parses the database file of any supported version, older formats are upgraded in memory
+  /// and written in the current format on the next save
   pub fn data_from_json(json: &str) -> JsonDataBaseResult<DatabaseData> {
-    use serde_json::from_str;
+    use serde_json::{from_str, from_value, Value};
 
-    from_str(json)
-    .report()
-    .attach_printable_lazy(|| {
+    let attach_json = || {
       let mut s = String::from("json:\n");
       s.push_str(json);
       s
-    })
+    };
+
+    let mut document: Value = from_str(json)
+    .report()
+    .attach_printable_lazy(attach_json)
+    .change_context(JsonDatabaseError::Deserialization)?;
+
+    upgrade_impl::upgrade(&mut document)?;
+
+    from_value(document)
+    .report()
+    .attach_printable_lazy(attach_json)
     .change_context(JsonDatabaseError::Deserialization)
   }
+
+  pub fn check_funds(funds: Money) -> DatabaseResult<()> {
+    if funds.is_positive() {
+      return Ok(());
+    }
+
+    Err(Report::new(JsonDatabaseError::InvalidAmount))
+      .attach_printable_lazy(|| {
+        format!("funds: {}", funds)
+      })
+      .change_context(DatabaseError::JSON)
+  }
+
+  pub fn check_different_cards(sender_card_number: &str, receiver_card_number: &str) -> DatabaseResult<()> {
+    if sender_card_number != receiver_card_number {
+      return Ok(());
+    }
+
+    Err(Report::new(JsonDatabaseError::TransferToSameCard))
+      .attach_printable_lazy(|| {
+        format!("card_number: {}", sender_card_number)
+      })
+      .change_context(DatabaseError::JSON)
+  }
+
+  /// Returns new balance of the client
+  pub fn add_to_balance(client: &mut Client, funds: Money) -> DatabaseResult<Money> {
+    match client.balance.checked_add(funds) {
+      None => Err(Report::new(JsonDatabaseError::BalanceOverflow))
+        .attach_printable_lazy(|| {
+          format!(
+            "balance of card_number: {} is {}, added funds: {}",
+            client.card_number,
+            client.balance,
+            funds
+          )
+        })
+        .change_context(DatabaseError::JSON),
+      Some(balance) => {
+        client.balance = balance;
+        Ok(balance)
+      },
+    }
+  }
+
+  pub fn get_client_mut<'a>(data: &'a mut DatabaseData, card_number: &str) -> DatabaseResult<&'a mut Client> {
+    match data.clients.get_mut(card_number) {
+      None => Err(Report::new(JsonDatabaseError::ClientNotFound))
+        .attach_printable_lazy(|| {
+          format!("client with card_number: {} not found", card_number)
+        })
+        .change_context(DatabaseError::JSON),
+      Some(client) => Ok(client),
+    }
+  }
+}
+
+mod upgrade_impl {
+  use super::*;
+  use serde_json::{json, Map, Value};
+
+  type Document = Map<String, Value>;
+
+  /// Upgrade at index N converts the document from version N to N + 1.
+  /// Files written before the `version` field was introduced are read as version 0,
+  /// so each step has to leave parts which are already in the newer format untouched.
+  pub const UPGRADES: &[fn(&mut Document)] = &[
+    add_ledger,
+    money_to_decimal_strings,
+    add_currencies,
+  ];
+
+  pub fn upgrade(document: &mut Value) -> JsonDataBaseResult<()> {
+    let document = document.as_object_mut()
+      .ok_or_else(|| Report::new(JsonDatabaseError::Deserialization))
+      .attach_printable("database file is not a json object")?;
+
+    let version = match document.get("version") {
+      None => 0,
+      Some(version) => version.as_u64()
+        .ok_or_else(|| Report::new(JsonDatabaseError::Deserialization))
+        .attach_printable_lazy(|| {
+          format!("invalid version: {version}")
+        })?,
+    };
+
+    if version > FORMAT_VERSION as u64 {
+      return Err(Report::new(JsonDatabaseError::UnsupportedVersion(version)));
+    }
+
+    for upgrade in &UPGRADES[version as usize..] {
+      upgrade(document);
+    }
+
+    document.insert(String::from("version"), json!(FORMAT_VERSION));
+
+    Ok(())
+  }
+
+  /// Version 1, transactions ledger and failed login attempts
+  fn add_ledger(document: &mut Document) {
+    document.entry("transactions").or_insert_with(|| json!([]));
+    document.entry("login_attempts").or_insert_with(|| json!({}));
+  }
+
+  /// Version 2, money as decimal strings instead of integer number of whole units
+  fn money_to_decimal_strings(document: &mut Document) {
+    for client in objects_mut(document, "clients") {
+      units_to_decimal_string(client.get_mut("balance"));
+    }
+
+    for transaction in objects_mut(document, "transactions") {
+      units_to_decimal_string(transaction.get_mut("amount"));
+      units_to_decimal_string(transaction.get_mut("balance"));
+    }
+  }
+
+  fn units_to_decimal_string(value: Option<&mut Value>) {
+    let Some(value) = value else {
+      return;
+    };
+
+    if let Some(money) = value.as_i64().and_then(Money::from_units) {
+      *value = json!(money.to_string());
+    }
+  }
+
+  /// Version 3, account currencies, older accounts are kept in PLN
+  fn add_currencies(document: &mut Document) {
+    for client in objects_mut(document, "clients") {
+      client.entry("currency").or_insert_with(|| json!(Currency::PLN));
+    }
+  }
+
+  /// Objects stored in the map or array under `key`, values of other shapes are left for serde to reject
+  fn objects_mut<'a>(document: &'a mut Document, key: &str) -> Vec<&'a mut Document> {
+    let values: Vec<&mut Value> = match document.get_mut(key) {
+      Some(Value::Object(map)) => map.values_mut().collect(),
+      Some(Value::Array(array)) => array.iter_mut().collect(),
+      _ => Vec::new(),
+    };
+
+    values.into_iter()
+      .filter_map(Value::as_object_mut)
+      .collect()
+  }
 }
 
 mod fs_impl {
@@ -289,36 +862,159 @@ mod fs_impl {
   use std::fs::OpenOptions;
   use std::io::prelude::*;
 
-  const FILE_NAME: &str = "data.json";
+  pub fn backup_path(path: &Path, generation: u32) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(format!(".bak.{}", generation));
 
-  pub fn read_json_file() -> JsonDataBaseResult<String> {
-    std::fs::read_to_string(FILE_NAME)
+    PathBuf::from(backup_path)
+  }
+
+  /// Lock is taken on separate `<file>.lock` file, because database file is replaced on every write
+  pub fn lock_file(path: &Path, kind: LockKind, timeout: Duration) -> JsonDataBaseResult<FileLock> {
+    use std::fs::TryLockError;
+    use std::time::Instant;
+
+    const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+
+    let file = OpenOptions::new()
+      .create(true)
+      .truncate(false)
+      .write(true)
+      .open(&lock_path)
+      .report()
+      .attach_printable(format!("failed to open lock file: {}", lock_path.display()))
+      .change_context(JsonDatabaseError::LockingFailed)?;
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+      let result = match kind {
+        LockKind::Shared => file.try_lock_shared(),
+        LockKind::Exclusive => file.try_lock(),
+      };
+
+      match result {
+        Ok(()) => return Ok(FileLock { file: Some(file), kind }),
+        Err(TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(RETRY_INTERVAL),
+        Err(TryLockError::WouldBlock) => {
+          return Err(Report::new(JsonDatabaseError::LockTimeout))
+            .attach_printable(format!("lock file: {}, waited: {:?}", lock_path.display(), timeout));
+        },
+        Err(TryLockError::Error(error)) => {
+          return Err(error)
+            .report()
+            .attach_printable(format!("failed to lock file: {}", lock_path.display()))
+            .change_context(JsonDatabaseError::LockingFailed);
+        },
+      }
+    }
+  }
+
+  pub fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+
+    #[cfg(unix)]
+    let file_id = {
+      use std::os::unix::fs::MetadataExt;
+
+      (metadata.dev(), metadata.ino())
+    };
+    #[cfg(not(unix))]
+    let file_id = (0, 0);
+
+    Some(FileStamp {
+      modified: metadata.modified().ok()?,
+      len: metadata.len(),
+      file_id,
+    })
+  }
+
+  pub fn read_json_file(path: &Path) -> JsonDataBaseResult<String> {
+    std::fs::read_to_string(path)
     .report()
-    .attach_printable(format!("failed to read file {}, file not exists?", FILE_NAME))
+    .attach_printable(format!("failed to read file {}, file not exists?", path.display()))
     .change_context(JsonDatabaseError::ReadingDatabaseFile)
   }
 
-  pub fn write_json_to_file(json: &str) -> JsonDataBaseResult<()> {
+  /// Writes to temporary file first and renames it over the database file,
+  /// so the database file is never partially written
+  pub fn write_json_to_file(path: &Path, json: &str) -> JsonDataBaseResult<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
     let mut file = OpenOptions::new()
       .create(true)
       .truncate(true)
       .write(true)
-      .open(FILE_NAME)
+      .open(&tmp_path)
       .report()
-      .attach_printable(format!("failed to open file: {}", FILE_NAME))
+      .attach_printable(format!("failed to open file: {}", tmp_path.display()))
       .change_context(JsonDatabaseError::SavingDatabaseFile)?;
 
-    let mut json_copy = String::from(json.clone());
+    let mut json_copy = String::from(json);
     json_copy.push('\n');
     let json_bytes = json_copy.as_bytes();
 
     file.write_all(json_bytes)
+    .and_then(|()| file.sync_all())
     .report()
-    .attach_printable(format!("failed write to file: {}", FILE_NAME))
+    .attach_printable(format!("failed write to file: {}", tmp_path.display()))
     .change_context(JsonDatabaseError::SavingDatabaseFile)?;
 
+    rotate_backups(path)?;
+
+    std::fs::rename(&tmp_path, path)
+    .report()
+    .attach_printable(format!("failed to rename {} to {}", tmp_path.display(), path.display()))
+    .change_context(JsonDatabaseError::SavingDatabaseFile)?;
+
+    sync_parent_dir(path);
+
     Ok(())
   }
+
+  /// Shifts backups by one generation and links current database file as the newest backup
+  fn rotate_backups(path: &Path) -> JsonDataBaseResult<()> {
+    if !path.exists() {
+      return Ok(());
+    }
+
+    for generation in (1..BACKUP_GENERATIONS).rev() {
+      let backup = backup_path(path, generation);
+
+      if backup.exists() {
+        std::fs::rename(&backup, backup_path(path, generation + 1))
+        .report()
+        .attach_printable(format!("failed to rotate backup: {}", backup.display()))
+        .change_context(JsonDatabaseError::SavingDatabaseFile)?;
+      }
+    }
+
+    let newest_backup = backup_path(path, 1);
+
+    std::fs::hard_link(path, &newest_backup)
+    .or_else(|_| std::fs::copy(path, &newest_backup).map(|_| ()))
+    .report()
+    .attach_printable(format!("failed to create backup: {}", newest_backup.display()))
+    .change_context(JsonDatabaseError::SavingDatabaseFile)
+  }
+
+  /// Makes the rename durable, not supported on every platform so errors are ignored
+  fn sync_parent_dir(path: &Path) {
+    let dir = match path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+
+    if let Ok(dir) = std::fs::File::open(dir) {
+      let _ = dir.sync_all();
+    }
+  }
 }
 
 #[cfg(test)]
@@ -351,9 +1047,17 @@ pub mod tests {
 
         Ok(())
       }),
+      read_backup_file: Box::new(|_generation| {
+        Err(Report::new(JsonDatabaseError::ReadingDatabaseFile))
+      }),
+      lock_file: Box::new(|kind| Ok(FileLock { file: None, kind })),
+      file_stamp: Box::new(|| None),
+      cache: RefCell::new(None),
     };
 
-    let data = json_db.read_data().unwrap();
+    let lock = json_db.lock(LockKind::Exclusive).unwrap();
+    let data = json_db.read_data(&lock).unwrap();
+    drop(lock);
 
     assert_eq!(data, DatabaseData::new());
 
@@ -383,4 +1087,366 @@ pub mod tests {
 
     json_db.save_new_client(client_mock).unwrap();
   }
+
+  #[test]
+  fn should_record_history() {
+    let client_mock = crate::database::tests::get_mock_client();
+    let mut receiver_mock = crate::database::tests::get_mock_client();
+    receiver_mock.card_number = String::from("4000000000000001");
+
+    let card_number = client_mock.card_number.clone();
+    let receiver_card_number = receiver_mock.card_number.clone();
+
+    let mut json_db = get_mock_db();
+
+    json_db.save_new_client(client_mock).unwrap();
+    json_db.save_new_client(receiver_mock).unwrap();
+    json_db.add_funds(Money::from_minor_units(500), &card_number).unwrap();
+    json_db.transfer_funds(
+      Money::from_minor_units(200),
+      &card_number,
+      &receiver_card_number,
+      &ExchangeRates::default()
+    ).unwrap();
+    json_db.remove_client(&receiver_card_number).unwrap();
+
+    let history = json_db.get_history(&card_number).unwrap();
+    let kinds: Vec<TransactionKind> = history.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, [TransactionKind::Deposit, TransactionKind::TransferOut]);
+    assert_eq!(history[1].amount, Money::from_minor_units(200));
+    assert_eq!(history[1].balance, Money::from_minor_units(300));
+    assert_eq!(history[1].counterparty.as_deref(), Some(receiver_card_number.as_str()));
+
+    let receiver_history = json_db.get_history(&receiver_card_number).unwrap();
+    let kinds: Vec<TransactionKind> = receiver_history.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, [TransactionKind::TransferIn, TransactionKind::AccountClosed]);
+    assert_eq!(receiver_history[1].amount, Money::from_minor_units(200));
+    assert_eq!(receiver_history[1].balance, Money::ZERO);
+  }
+
+  #[test]
+  fn should_store_database_in_given_file() {
+    let path = std::env::temp_dir()
+      .join(format!("rust-bank-json-{}", std::process::id()))
+      .join("bank.json");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+    let mut json_db = JsonDb::new(&path);
+    json_db.save_new_client(crate::database::tests::get_mock_client()).unwrap();
+
+    let reopened_db = JsonDb::new(&path);
+    assert_eq!(reopened_db.get_clients_count().unwrap(), 1);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn should_keep_backup_generations() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-backups-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bank.json");
+
+    let mut json_db = JsonDb::new(&path);
+    let mut client = crate::database::tests::get_mock_client();
+
+    for i in 0..5 {
+      client.card_number = format!("400000000000000{}", i);
+      json_db.save_new_client(client.clone()).unwrap();
+    }
+
+    let backups: Vec<u32> = (1..=BACKUP_GENERATIONS + 1)
+      .filter(|generation| fs_impl::backup_path(&path, *generation).exists())
+      .collect();
+    assert_eq!(backups, (1..=BACKUP_GENERATIONS).collect::<Vec<_>>());
+
+    let newest_backup = std::fs::read_to_string(fs_impl::backup_path(&path, 1)).unwrap();
+    let newest_backup = json_impl::data_from_json(&newest_backup).unwrap();
+    assert_eq!(newest_backup.clients.len(), 4);
+
+    std::fs::write(&path, "{\"clients\": {").unwrap();
+
+    let recovered_db = JsonDb::new(&path);
+    assert_eq!(recovered_db.get_clients_count().unwrap(), 4);
+    assert_eq!(JsonDb::new(&path).get_clients_count().unwrap(), 4);
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(JsonDb::new(&path).get_clients_count().unwrap(), 4);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn should_time_out_waiting_for_lock() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-lock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bank.json");
+    let timeout = Duration::from_millis(50);
+
+    let shared_lock = fs_impl::lock_file(&path, LockKind::Shared, timeout).unwrap();
+    fs_impl::lock_file(&path, LockKind::Shared, timeout).unwrap();
+
+    let report = fs_impl::lock_file(&path, LockKind::Exclusive, timeout).err().unwrap();
+    assert!(matches!(report.current_context(), JsonDatabaseError::LockTimeout));
+
+    drop(shared_lock);
+
+    let exclusive_lock = fs_impl::lock_file(&path, LockKind::Exclusive, timeout).unwrap();
+    let report = fs_impl::lock_file(&path, LockKind::Shared, timeout).err().unwrap();
+    assert!(matches!(report.current_context(), JsonDatabaseError::LockTimeout));
+
+    drop(exclusive_lock);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn should_not_lose_concurrent_updates() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-concurrent-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bank.json");
+
+    let client = crate::database::tests::get_mock_client();
+    let card_number = client.card_number.clone();
+    JsonDb::new(&path).save_new_client(client).unwrap();
+
+    let threads: Vec<_> = (0..4)
+      .map(|_| {
+        let path = path.clone();
+        let card_number = card_number.clone();
+
+        std::thread::spawn(move || {
+          let mut json_db = JsonDb::new(&path);
+
+          for _ in 0..10 {
+            json_db.add_funds(Money::from_minor_units(1), &card_number).unwrap();
+          }
+        })
+      })
+      .collect();
+
+    for thread in threads {
+      thread.join().unwrap();
+    }
+
+    let json_db = JsonDb::new(&path);
+    assert_eq!(json_db.get_client(&card_number).unwrap().balance, Money::from_minor_units(40));
+    assert_eq!(json_db.get_history(&card_number).unwrap().len(), 40);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn should_read_file_only_when_it_changed() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut json_db = get_mock_db();
+    json_db.save_new_client(crate::database::tests::get_mock_client()).unwrap();
+
+    let lock = json_db.lock(LockKind::Shared).unwrap();
+    let saved_json = json_impl::data_to_json_str(&json_db.data(&lock).unwrap()).unwrap();
+    drop(lock);
+    let reads = Rc::new(Cell::new(0));
+    let reads_copy = reads.clone();
+    let stamp = Rc::new(Cell::new(FileStamp { modified: SystemTime::UNIX_EPOCH, len: 1, file_id: (0, 0) }));
+    let stamp_copy = stamp.clone();
+
+    json_db.read_json_file = Box::new(move || {
+      reads_copy.set(reads_copy.get() + 1);
+      Ok(saved_json.clone())
+    });
+    json_db.file_stamp = Box::new(move || Some(stamp_copy.get()));
+    json_db.cache.replace(None);
+
+    for _ in 0..3 {
+      assert!(json_db.has_client("4000000000000000").unwrap());
+    }
+    assert_eq!(reads.get(), 1);
+
+    stamp.set(FileStamp { modified: SystemTime::UNIX_EPOCH, len: 1, file_id: (0, 1) });
+
+    json_db.get_client("4000000000000000").unwrap();
+    json_db.add_funds(Money::from_minor_units(100), "4000000000000000").unwrap();
+    json_db.get_client("4000000000000000").unwrap();
+    assert_eq!(reads.get(), 2);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn should_change_stamp_when_file_is_replaced_with_same_length() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-stamp-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bank.json");
+
+    fs_impl::write_json_to_file(&path, "{\"pin\": \"1234\"}").unwrap();
+    let stamp = fs_impl::file_stamp(&path).unwrap();
+
+    fs_impl::write_json_to_file(&path, "{\"pin\": \"5678\"}").unwrap();
+    let new_stamp = fs_impl::file_stamp(&path).unwrap();
+
+    assert_eq!(stamp.len, new_stamp.len);
+    assert_ne!(stamp, new_stamp);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn should_recover_from_newest_valid_backup() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut json_db = get_mock_db();
+    let writes = Rc::new(Cell::new(0));
+    let writes_copy = writes.clone();
+
+    json_db.write_json_to_file = Box::new(move |_json| {
+      writes_copy.set(writes_copy.get() + 1);
+      Ok(())
+    });
+    json_db.read_json_file = Box::new(|| Ok(String::from("not json")));
+    json_db.read_backup_file = Box::new(|generation| {
+      match generation {
+        1 => Ok(String::from("{\"clients\": [")),
+        2 => Ok(String::from("{\"clients\": {}}")),
+        _ => panic!("older backup read, generation: {generation}"),
+      }
+    });
+
+    // readers share the lock, so only the next writer replaces the broken file
+    let shared_lock = json_db.lock(LockKind::Shared).unwrap();
+    assert_eq!(json_db.read_data(&shared_lock).unwrap(), DatabaseData::new());
+    assert_eq!(writes.get(), 0);
+    drop(shared_lock);
+
+    let exclusive_lock = json_db.lock(LockKind::Exclusive).unwrap();
+    assert_eq!(json_db.read_data(&exclusive_lock).unwrap(), DatabaseData::new());
+    assert_eq!(writes.get(), 1);
+
+    json_db.read_backup_file = Box::new(|_generation| Ok(String::from("not json")));
+
+    let report = json_db.read_data(&exclusive_lock).unwrap_err();
+    assert!(matches!(report.current_context(), JsonDatabaseError::Deserialization));
+  }
+
+  #[test]
+  fn should_create_new_file_only_when_it_and_backups_are_missing() {
+    use std::cell::Cell;
+    use std::io::ErrorKind as IoErrorKind;
+    use std::rc::Rc;
+
+    fn io_error(kind: IoErrorKind) -> JsonDataBaseResult<String> {
+      Err(std::io::Error::from(kind))
+        .report()
+        .change_context(JsonDatabaseError::ReadingDatabaseFile)
+    }
+
+    let mut json_db = get_mock_db();
+    let writes = Rc::new(Cell::new(0));
+    let writes_copy = writes.clone();
+
+    json_db.write_json_to_file = Box::new(move |_json| {
+      writes_copy.set(writes_copy.get() + 1);
+      Ok(())
+    });
+
+    let lock = json_db.lock(LockKind::Exclusive).unwrap();
+
+    for kind in [IoErrorKind::PermissionDenied, IoErrorKind::IsADirectory, IoErrorKind::Interrupted] {
+      json_db.read_json_file = Box::new(move || io_error(kind));
+
+      let report = json_db.read_data(&lock).unwrap_err();
+      assert!(matches!(report.current_context(), JsonDatabaseError::ReadingDatabaseFile), "{kind:?}");
+    }
+
+    // missing file is restored from backups, broken backups are an error, not a new empty database
+    json_db.read_json_file = Box::new(|| io_error(IoErrorKind::NotFound));
+    json_db.read_backup_file = Box::new(|generation| match generation {
+      2 => Ok(String::from("not json")),
+      _ => io_error(IoErrorKind::NotFound),
+    });
+
+    let report = json_db.read_data(&lock).unwrap_err();
+    assert!(matches!(report.current_context(), JsonDatabaseError::ReadingDatabaseFile));
+    assert_eq!(writes.get(), 0);
+
+    json_db.read_backup_file = Box::new(|_generation| io_error(IoErrorKind::NotFound));
+
+    assert_eq!(json_db.read_data(&lock).unwrap(), DatabaseData::new());
+    assert_eq!(writes.get(), 1);
+  }
+
+  const FIXTURES: [&str; 4] = [
+    include_str!("fixtures/data_v0.json"),
+    include_str!("fixtures/data_v1.json"),
+    include_str!("fixtures/data_v2.json"),
+    include_str!("fixtures/data_v3.json"),
+  ];
+
+  #[test]
+  fn should_upgrade_every_format_version() {
+    assert_eq!(FIXTURES.len(), FORMAT_VERSION as usize + 1);
+
+    let current = json_impl::data_from_json(FIXTURES[FORMAT_VERSION as usize]).unwrap();
+    assert_eq!(current.version, FORMAT_VERSION);
+    assert_eq!(current.transactions.len(), 3);
+
+    for (version, fixture) in FIXTURES.iter().enumerate() {
+      let mut data = json_impl::data_from_json(fixture).unwrap();
+
+      // version 0 had no ledger yet
+      if version == 0 {
+        data.transactions = current.transactions.clone();
+        data.login_attempts = current.login_attempts.clone();
+      }
+
+      assert_eq!(data, current, "version: {version}");
+    }
+  }
+
+  #[test]
+  fn should_write_current_format_version() {
+    let current = FIXTURES[FORMAT_VERSION as usize];
+    let data = json_impl::data_from_json(FIXTURES[1]).unwrap();
+
+    assert_eq!(json_impl::data_to_json_str(&data).unwrap(), current.trim_end());
+  }
+
+  #[test]
+  fn should_refuse_newer_format_version() {
+    let newer_json = format!("{{\"version\": {}, \"clients\": {{}}}}", FORMAT_VERSION + 1);
+
+    let mut json_db = get_mock_db();
+    json_db.read_json_file = Box::new(move || Ok(newer_json.clone()));
+    json_db.read_backup_file = Box::new(|generation| {
+      panic!("backup read for file of newer version, generation: {generation}")
+    });
+
+    let lock = json_db.lock(LockKind::Exclusive).unwrap();
+    let report = json_db.read_data(&lock).unwrap_err();
+    assert!(matches!(
+      report.current_context(),
+      JsonDatabaseError::UnsupportedVersion(version) if *version == FORMAT_VERSION as u64 + 1
+    ));
+  }
+
+  #[test]
+  fn should_read_legacy_balances_as_whole_units() {
+    let legacy_json = r#"{
+      "clients": {
+        "4000000000000000": {
+          "card_number": "4000000000000000",
+          "pin": "1234",
+          "balance": 12
+        }
+      }
+    }"#;
+
+    let data = json_impl::data_from_json(legacy_json).unwrap();
+    let client = data.clients.get("4000000000000000").unwrap();
+    assert_eq!(client.balance, Money::from_minor_units(1200));
+
+    let json = json_impl::data_to_json_str(&data).unwrap();
+    let data = json_impl::data_from_json(&json).unwrap();
+    let client = data.clients.get("4000000000000000").unwrap();
+    assert_eq!(client.balance, Money::from_minor_units(1200));
+  }
 }
@@ -0,0 +1,408 @@
+use crate::Database;
+use crate::Client;
+use crate::database::{DatabaseData, ErrorKind, LoginAttempts, Snapshot, Transaction, TransactionKind};
+use crate::money::Money;
+use crate::currency::ExchangeRates;
+use crate::{DatabaseError, DatabaseResult};
+
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum MemoryDatabaseError {
+  ReadingSeedFile,
+  ParsingSeedFile,
+  ClientNotFound,
+  InsufficientFunds,
+  InvalidAmount,
+  BalanceOverflow,
+  CurrencyConversionFailed,
+  TransferToSameCard,
+  ClientAlreadyExists(String),
+}
+
+pub type MemoryDataBaseResult<T> = Result<T, MemoryDatabaseError>;
+
+impl fmt::Display for MemoryDatabaseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Self::ReadingSeedFile => write!(f, "reading seed file failed"),
+      Self::ParsingSeedFile => write!(f, "seed file is malformed"),
+      Self::ClientNotFound => write!(f, "client not found in database"),
+      Self::InsufficientFunds => write!(f, "operation failed due to insufficient funds"),
+      Self::InvalidAmount => write!(f, "amount must be positive"),
+      Self::BalanceOverflow => write!(f, "balance would exceed maximal value"),
+      Self::CurrencyConversionFailed => write!(f, "currency conversion failed"),
+      Self::TransferToSameCard => write!(f, "sender and receiver card numbers are the same"),
+      Self::ClientAlreadyExists(card_number) => write!(f, "client with {card_number} already exists in database"),
+    }
+  }
+}
+
+impl Context for MemoryDatabaseError {}
+
+impl MemoryDatabaseError {
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::ClientNotFound => ErrorKind::ClientNotFound,
+      Self::ClientAlreadyExists(_) => ErrorKind::ClientAlreadyExists,
+      Self::InsufficientFunds => ErrorKind::InsufficientFunds,
+      Self::InvalidAmount | Self::BalanceOverflow => ErrorKind::InvalidAmount,
+      Self::CurrencyConversionFailed => ErrorKind::CurrencyConversionFailed,
+      Self::TransferToSameCard => ErrorKind::InvalidArgument,
+      _ => ErrorKind::Other,
+    }
+  }
+}
+
+/// Database kept only in process memory, all changes are lost on exit
+#[derive(Debug, Default)]
+pub struct MemoryDb {
+  clients: BTreeMap<String, Client>,
+  transactions: Vec<Transaction>,
+  login_attempts: BTreeMap<String, LoginAttempts>,
+}
+
+impl MemoryDb {
+  /// Starts with the content of the seed file in data.json format, missing file means empty database
+  pub fn new(seed_path: &Path) -> Self {
+    match MemoryDb::from_seed_file(seed_path) {
+      Err(error) => {
+        eprintln!("\nfailed to load seed file, error: {:?}", error);
+        panic!("MemoryDb::new() failed");
+      },
+      Ok(db) => db,
+    }
+  }
+
+  fn from_seed_file(seed_path: &Path) -> MemoryDataBaseResult<Self> {
+    if !seed_path.exists() {
+      return Ok(MemoryDb::default());
+    }
+
+    let json = std::fs::read_to_string(seed_path)
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to read file {}", seed_path.display())
+      })
+      .change_context(MemoryDatabaseError::ReadingSeedFile)?;
+
+    let data = DatabaseData::from_json(&json)
+      .change_context(MemoryDatabaseError::ParsingSeedFile)
+      .attach_printable_lazy(|| {
+        format!("file: {}", seed_path.display())
+      })?;
+
+    Ok(MemoryDb {
+      clients: data.clients,
+      transactions: data.transactions,
+      login_attempts: data.login_attempts,
+    })
+  }
+
+  fn record_transaction(&mut self, mut transaction: Transaction) {
+    transaction.id = match self.transactions.last() {
+      None => 1,
+      Some(last) => last.id + 1,
+    };
+
+    self.transactions.push(transaction);
+  }
+
+  fn check_funds(funds: Money) -> DatabaseResult<()> {
+    if funds.is_positive() {
+      return Ok(());
+    }
+
+    Err(Report::new(MemoryDatabaseError::InvalidAmount))
+      .attach_printable_lazy(|| {
+        format!("funds: {}", funds)
+      })
+      .change_context(DatabaseError::Memory)
+  }
+
+  fn check_different_cards(sender_card_number: &str, receiver_card_number: &str) -> DatabaseResult<()> {
+    if sender_card_number != receiver_card_number {
+      return Ok(());
+    }
+
+    Err(Report::new(MemoryDatabaseError::TransferToSameCard))
+      .attach_printable_lazy(|| {
+        format!("card_number: {}", sender_card_number)
+      })
+      .change_context(DatabaseError::Memory)
+  }
+
+  fn client(&self, card_number: &str) -> DatabaseResult<&Client> {
+    self.clients.get(card_number)
+      .ok_or_else(|| Report::new(MemoryDatabaseError::ClientNotFound))
+      .attach_printable_lazy(|| {
+        format!("client with card_number: {} not found", card_number)
+      })
+      .change_context(DatabaseError::Memory)
+  }
+
+  fn client_mut(&mut self, card_number: &str) -> DatabaseResult<&mut Client> {
+    self.clients.get_mut(card_number)
+      .ok_or_else(|| Report::new(MemoryDatabaseError::ClientNotFound))
+      .attach_printable_lazy(|| {
+        format!("client with card_number: {} not found", card_number)
+      })
+      .change_context(DatabaseError::Memory)
+  }
+
+  fn added_balance(client: &Client, funds: Money) -> DatabaseResult<Money> {
+    client.balance.checked_add(funds)
+      .ok_or_else(|| Report::new(MemoryDatabaseError::BalanceOverflow))
+      .attach_printable_lazy(|| {
+        format!(
+          "balance of card_number: {} is {}, added funds: {}",
+          client.card_number,
+          client.balance,
+          funds
+        )
+      })
+      .change_context(DatabaseError::Memory)
+  }
+}
+
+impl Database for MemoryDb {
+  fn name(&self) -> &str {
+    "memory"
+  }
+
+  fn save_new_client(&mut self, client: Client) -> DatabaseResult<()> {
+    if self.clients.contains_key(&client.card_number) {
+      return Err(Report::new(MemoryDatabaseError::ClientAlreadyExists(client.card_number)))
+        .change_context(DatabaseError::Memory);
+    }
+
+    self.clients.insert(client.card_number.clone(), client);
+
+    Ok(())
+  }
+
+  fn has_client(&self, card_number: &str) -> DatabaseResult<bool> {
+    Ok(self.clients.contains_key(card_number))
+  }
+
+  fn get_client(&self, card_number: &str) -> DatabaseResult<Client> {
+    self.client(card_number).cloned()
+  }
+
+  fn remove_client(&mut self, card_number: &str) -> DatabaseResult<Client> {
+    self.client(card_number)?;
+
+    let client = self.clients.remove(card_number).expect("client checked above");
+
+    self.login_attempts.remove(card_number);
+
+    self.record_transaction(
+      Transaction::new(card_number, TransactionKind::AccountClosed, client.balance, Money::ZERO)
+    );
+
+    Ok(client)
+  }
+
+  fn add_funds(&mut self, funds: Money, card_number: &str) -> DatabaseResult<()> {
+    MemoryDb::check_funds(funds)?;
+
+    let balance = MemoryDb::added_balance(self.client(card_number)?, funds)?;
+
+    self.client_mut(card_number)?.balance = balance;
+
+    self.record_transaction(
+      Transaction::new(card_number, TransactionKind::Deposit, funds, balance)
+    );
+
+    Ok(())
+  }
+
+  fn update_pin(&mut self, card_number: &str, pin: &str) -> DatabaseResult<()> {
+    self.client_mut(card_number)?.pin = pin.to_owned();
+
+    Ok(())
+  }
+
+  fn transfer_funds(
+    &mut self,
+    funds: Money,
+    sender_card_number: &str,
+    receiver_card_number: &str,
+    rates: &ExchangeRates
+  ) -> DatabaseResult<()> {
+    MemoryDb::check_funds(funds)?;
+    MemoryDb::check_different_cards(sender_card_number, receiver_card_number)?;
+
+    let sender_client = self.client(sender_card_number)
+      .attach_printable_lazy(|| {
+        format!("sender client not found, sender_card_number: {}", sender_card_number)
+      })?;
+    let receiver_client = self.client(receiver_card_number)
+      .attach_printable_lazy(|| {
+        format!("receiver client not found, receiver_card_number: {}", receiver_card_number)
+      })?;
+
+    let (received_funds, exchange_rate) = rates.convert(funds, sender_client.currency, receiver_client.currency)
+      .change_context(MemoryDatabaseError::CurrencyConversionFailed)
+      .change_context(DatabaseError::Memory)?;
+
+    let sender_balance = match sender_client.balance.checked_sub(funds) {
+      Some(balance) if !balance.is_negative() => balance,
+      _ => return Err(Report::new(MemoryDatabaseError::InsufficientFunds))
+        .attach_printable_lazy(|| {
+          format!(
+            "sender's balance before transfer: {}, transferred funds: {}",
+            sender_client.balance,
+            funds
+          )
+        })
+        .change_context(DatabaseError::Memory),
+    };
+
+    // checked before any change, so failed transfer leaves both balances untouched
+    let receiver_balance = MemoryDb::added_balance(receiver_client, received_funds)?;
+
+    self.client_mut(sender_card_number)?.balance = sender_balance;
+    self.client_mut(receiver_card_number)?.balance = receiver_balance;
+
+    self.record_transaction(
+      Transaction::new(sender_card_number, TransactionKind::TransferOut, funds, sender_balance)
+        .with_counterparty(receiver_card_number, exchange_rate)
+    );
+    self.record_transaction(
+      Transaction::new(receiver_card_number, TransactionKind::TransferIn, received_funds, receiver_balance)
+        .with_counterparty(sender_card_number, exchange_rate)
+    );
+
+    Ok(())
+  }
+
+  fn get_login_attempts(&self, card_number: &str) -> DatabaseResult<LoginAttempts> {
+    Ok(self.login_attempts.get(card_number).copied().unwrap_or_default())
+  }
+
+  fn register_failed_login(&mut self, card_number: &str, max_attempts: u32) -> DatabaseResult<LoginAttempts> {
+    self.client(card_number)?;
+
+    let attempts = self.login_attempts
+      .entry(card_number.to_owned())
+      .or_default();
+
+    attempts.failed += 1;
+    attempts.locked = attempts.locked || attempts.failed >= max_attempts;
+
+    Ok(*attempts)
+  }
+
+  fn reset_login_attempts(&mut self, card_number: &str) -> DatabaseResult<()> {
+    self.client(card_number)?;
+
+    self.login_attempts.remove(card_number);
+
+    Ok(())
+  }
+
+  fn get_clients_count(&self) -> DatabaseResult<u32> {
+    Ok(self.clients.len() as u32)
+  }
+
+  fn get_history(&self, card_number: &str) -> DatabaseResult<Vec<Transaction>> {
+    let history = self.transactions
+      .iter()
+      .filter(|transaction| transaction.card_number == card_number)
+      .cloned()
+      .collect();
+
+    Ok(history)
+  }
+
+  fn export(&self) -> DatabaseResult<Snapshot> {
+    Ok(Snapshot {
+      clients: self.clients.values().cloned().collect(),
+      login_attempts: self.login_attempts.clone(),
+      transactions: self.transactions.clone(),
+    })
+  }
+
+  fn import(&mut self, snapshot: Snapshot) -> DatabaseResult<()> {
+    if let Some(client) = snapshot.clients.iter().find(|client| self.clients.contains_key(&client.card_number)) {
+      return Err(Report::new(MemoryDatabaseError::ClientAlreadyExists(client.card_number.clone())))
+        .change_context(DatabaseError::Memory);
+    }
+
+    for client in snapshot.clients {
+      self.clients.insert(client.card_number.clone(), client);
+    }
+
+    self.login_attempts.extend(snapshot.login_attempts);
+
+    for transaction in snapshot.transactions {
+      self.record_transaction(transaction);
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+
+  pub fn get_mock_db() -> MemoryDb {
+    MemoryDb::default()
+  }
+
+  #[test]
+  fn should_start_empty_without_seed_file() {
+    let memory_db = MemoryDb::new(Path::new("missing-seed-file.json"));
+
+    assert_eq!(memory_db.get_clients_count().unwrap(), 0);
+  }
+
+  #[test]
+  fn should_load_seed_file() {
+    let path = std::env::temp_dir().join(format!("rust-bank-seed-{}.json", std::process::id()));
+    std::fs::write(&path, include_str!("fixtures/data_v1.json")).unwrap();
+
+    let mut memory_db = MemoryDb::new(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(memory_db.get_clients_count().unwrap(), 2);
+    assert_eq!(memory_db.get_client("4000000000000000").unwrap().balance, Money::from_minor_units(700));
+    assert_eq!(memory_db.get_login_attempts("4000000000000001").unwrap().failed, 1);
+
+    memory_db.add_funds(Money::from_minor_units(100), "4000000000000000").unwrap();
+
+    let history = memory_db.get_history("4000000000000000").unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.last().unwrap().id, 4);
+  }
+
+  #[test]
+  fn should_leave_balances_untouched_after_failed_transfer() {
+    let mut memory_db = get_mock_db();
+    let sender = crate::database::tests::get_mock_client();
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000001");
+    receiver.balance = Money::from_minor_units(i64::MAX);
+
+    memory_db.save_new_client(sender.clone()).unwrap();
+    memory_db.save_new_client(receiver.clone()).unwrap();
+    memory_db.add_funds(Money::from_minor_units(100), &sender.card_number).unwrap();
+
+    let report = memory_db.transfer_funds(
+      Money::from_minor_units(50),
+      &sender.card_number,
+      &receiver.card_number,
+      &ExchangeRates::default()
+    ).unwrap_err();
+
+    assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount);
+    assert_eq!(memory_db.get_client(&sender.card_number).unwrap().balance, Money::from_minor_units(100));
+    assert_eq!(memory_db.get_history(&receiver.card_number).unwrap().len(), 0);
+  }
+}
@@ -0,0 +1,180 @@
+use crate::database::{Database, Snapshot};
+use crate::money::Money;
+use crate::currency::Currency;
+
+use error_stack::{Context, Report, Result, ResultExt};
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum MigrationError {
+  TargetNotEmpty,
+  ExportFailed,
+  ImportFailed,
+  BalanceOverflow,
+  VerificationFailed,
+}
+
+pub type MigrationResult<T> = Result<T, MigrationError>;
+
+impl fmt::Display for MigrationError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MigrationError::TargetNotEmpty => write!(f, "target database is not empty"),
+      MigrationError::ExportFailed => write!(f, "reading source database failed"),
+      MigrationError::ImportFailed => write!(f, "saving data to target database failed"),
+      MigrationError::BalanceOverflow => write!(f, "total balance is too large"),
+      MigrationError::VerificationFailed => write!(f, "target database content differs from source database"),
+    }
+  }
+}
+
+impl Context for MigrationError {}
+
+/// Numbers compared between source and target database after migration
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationSummary {
+  pub clients: usize,
+  pub locked_cards: usize,
+  pub transactions: usize,
+  /// Sum of balances of all accounts in each currency
+  pub total_balances: BTreeMap<Currency, Money>,
+}
+
+impl MigrationSummary {
+  fn of(snapshot: &Snapshot) -> MigrationResult<Self> {
+    let mut total_balances: BTreeMap<Currency, Money> = BTreeMap::new();
+
+    for client in &snapshot.clients {
+      let total = total_balances.entry(client.currency).or_default();
+
+      *total = total.checked_add(client.balance)
+        .ok_or_else(|| Report::new(MigrationError::BalanceOverflow))
+        .attach_printable_lazy(|| {
+          format!("currency: {}", client.currency)
+        })?;
+    }
+
+    Ok(MigrationSummary {
+      clients: snapshot.clients.len(),
+      locked_cards: snapshot.login_attempts.values().filter(|attempts| attempts.locked).count(),
+      transactions: snapshot.transactions.len(),
+      total_balances,
+    })
+  }
+}
+
+/// Copies clients, failed logins and the whole ledger into empty `target`,
+/// then reads the target back and checks that it matches the source
+pub fn migrate(source: &dyn Database, target: &mut dyn Database) -> MigrationResult<MigrationSummary> {
+  let target_snapshot = target.export()
+    .change_context(MigrationError::ExportFailed)
+    .attach_printable_lazy(|| {
+      format!("failed to check if {} target database is empty", target.name())
+    })?;
+
+  if target_snapshot != Snapshot::default() {
+    return Err(Report::new(MigrationError::TargetNotEmpty))
+      .attach_printable_lazy(|| {
+        format!(
+          "target contains {} clients and {} transactions",
+          target_snapshot.clients.len(),
+          target_snapshot.transactions.len()
+        )
+      });
+  }
+
+  let snapshot = source.export()
+    .change_context(MigrationError::ExportFailed)?;
+
+  let summary = MigrationSummary::of(&snapshot)?;
+
+  target.import(snapshot)
+    .change_context(MigrationError::ImportFailed)?;
+
+  let migrated_snapshot = target.export()
+    .change_context(MigrationError::ExportFailed)?;
+
+  let migrated_summary = MigrationSummary::of(&migrated_snapshot)?;
+
+  if migrated_summary != summary {
+    return Err(Report::new(MigrationError::VerificationFailed))
+      .attach_printable(format!("source: {summary:?}, target: {migrated_summary:?}"));
+  }
+
+  Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::currency::ExchangeRates;
+
+  #[test]
+  fn should_migrate_json_to_sqlite() {
+    migrate_data(
+      crate::database::json::tests::get_mock_db(),
+      crate::database::sqlite::tests::get_mock_db()
+    );
+  }
+
+  #[test]
+  fn should_migrate_sqlite_to_json() {
+    migrate_data(
+      crate::database::sqlite::tests::get_mock_db(),
+      crate::database::json::tests::get_mock_db()
+    );
+  }
+
+  fn migrate_data(mut source: impl Database, mut target: impl Database) {
+    let sender = crate::database::tests::get_mock_client();
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000001");
+    let mut closed = crate::database::tests::get_mock_client();
+    closed.card_number = String::from("4000000000000002");
+    let mut eur_client = crate::database::tests::get_mock_client();
+    eur_client.card_number = String::from("4000000000000003");
+    eur_client.currency = "EUR".parse().unwrap();
+
+    for client in [sender.clone(), receiver.clone(), closed.clone(), eur_client.clone()] {
+      source.save_new_client(client).unwrap();
+    }
+
+    source.add_funds(Money::from_minor_units(1000), &sender.card_number).unwrap();
+    source.add_funds(Money::from_minor_units(250), &eur_client.card_number).unwrap();
+    source.transfer_funds(
+      Money::from_minor_units(300),
+      &sender.card_number,
+      &receiver.card_number,
+      &ExchangeRates::default()
+    ).unwrap();
+    source.remove_client(&closed.card_number).unwrap();
+    source.register_failed_login(&receiver.card_number, 1).unwrap();
+
+    let summary = migrate(&source, &mut target).unwrap();
+
+    assert_eq!(summary.clients, 3);
+    assert_eq!(summary.locked_cards, 1);
+    assert_eq!(summary.transactions, 5);
+    assert_eq!(summary.total_balances[&Currency::PLN], Money::from_minor_units(1000));
+    assert_eq!(summary.total_balances[&eur_client.currency], Money::from_minor_units(250));
+
+    assert_eq!(target.get_client(&sender.card_number).unwrap(), source.get_client(&sender.card_number).unwrap());
+    assert!(target.get_login_attempts(&receiver.card_number).unwrap().locked);
+
+    for card_number in [&sender.card_number, &receiver.card_number, &closed.card_number] {
+      let kinds = |db: &dyn Database| {
+        db.get_history(card_number).unwrap()
+          .into_iter()
+          .map(|transaction| (transaction.kind, transaction.amount, transaction.timestamp))
+          .collect::<Vec<_>>()
+      };
+
+      assert_eq!(kinds(&target), kinds(&source), "card_number: {card_number}");
+    }
+
+    let report = migrate(&source, &mut target).unwrap_err();
+    assert!(matches!(report.current_context(), MigrationError::TargetNotEmpty));
+  }
+}
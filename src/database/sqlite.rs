@@ -2,12 +2,17 @@ use crate::Database;
 use crate::Client;
 use crate::DatabaseResult;
 use crate::DatabaseError;
+use crate::database::{ErrorKind, LoginAttempts, Snapshot, Transaction, TransactionKind};
+use crate::money::Money;
+use crate::currency::{Currency, ExchangeRates, Rate};
 
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension, TransactionBehavior};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 
 use error_stack::{Context, Result, IntoReport, Report, ResultExt};
 
 use std::fmt;
+use std::path::Path;
 
 pub type SQLiteDataBaseResult<T> = Result<T, SQLiteDatabaseError>;
 
@@ -16,6 +21,13 @@ pub enum SQLiteDatabaseError {
   ConnectionFailed,
   QueryFailed,
   PrepareQueryFailed,
+  ClientNotFound,
+  InsufficientFunds,
+  InvalidAmount,
+  BalanceOverflow,
+  CurrencyConversionFailed,
+  TransferToSameCard,
+  UnsupportedSchemaVersion(u32),
   ClientAlreadyExists(Client),
 }
 
@@ -25,6 +37,16 @@ impl fmt::Display for SQLiteDatabaseError {
       Self::ConnectionFailed => write!(f, "getting sqlite connection failed"),
       Self::QueryFailed => write!(f, "sqlite query failed"),
       Self::PrepareQueryFailed => write!(f, "prepare sqlite query failed"),
+      Self::ClientNotFound => write!(f, "client not found in database"),
+      Self::InsufficientFunds => write!(f, "operation failed due to insufficient funds"),
+      Self::InvalidAmount => write!(f, "amount must be positive"),
+      Self::BalanceOverflow => write!(f, "balance would exceed maximal value"),
+      Self::CurrencyConversionFailed => write!(f, "currency conversion failed"),
+      Self::TransferToSameCard => write!(f, "sender and receiver card numbers are the same"),
+      Self::UnsupportedSchemaVersion(version) => write!(
+        f,
+        "database schema version {version} is newer than supported version {SCHEMA_VERSION}"
+      ),
       Self::ClientAlreadyExists(client) => write!(f, "client already exists in database, {client:?}"),
     }
   }
@@ -32,21 +54,136 @@ impl fmt::Display for SQLiteDatabaseError {
 
 impl Context for SQLiteDatabaseError {}
 
+impl SQLiteDatabaseError {
+  pub fn kind(&self) -> ErrorKind {
+    match self {
+      Self::ClientNotFound => ErrorKind::ClientNotFound,
+      Self::ClientAlreadyExists(_) => ErrorKind::ClientAlreadyExists,
+      Self::InsufficientFunds => ErrorKind::InsufficientFunds,
+      Self::InvalidAmount | Self::BalanceOverflow => ErrorKind::InvalidAmount,
+      Self::CurrencyConversionFailed => ErrorKind::CurrencyConversionFailed,
+      Self::TransferToSameCard => ErrorKind::InvalidArgument,
+      _ => ErrorKind::Other,
+    }
+  }
+}
+
+impl ToSql for Money {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    Ok(ToSqlOutput::from(self.minor_units()))
+  }
+}
+
+impl FromSql for Money {
+  fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+    value.as_i64().map(Money::from_minor_units)
+  }
+}
+
+impl ToSql for Currency {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    Ok(ToSqlOutput::from(self.as_str()))
+  }
+}
+
+impl FromSql for Currency {
+  fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+    value.as_str()?
+      .parse()
+      .map_err(|_| FromSqlError::InvalidType)
+  }
+}
+
+impl ToSql for Rate {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    Ok(ToSqlOutput::from(self.micro_units()))
+  }
+}
+
+impl FromSql for Rate {
+  fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+    value.as_i64().map(Rate::from_micro_units)
+  }
+}
+
+impl ToSql for TransactionKind {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    let kind = match self {
+      TransactionKind::Deposit => "deposit",
+      TransactionKind::TransferIn => "transfer_in",
+      TransactionKind::TransferOut => "transfer_out",
+      TransactionKind::AccountClosed => "account_closed",
+    };
+
+    Ok(ToSqlOutput::from(kind))
+  }
+}
+
+impl FromSql for TransactionKind {
+  fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+    match value.as_str()? {
+      "deposit" => Ok(TransactionKind::Deposit),
+      "transfer_in" => Ok(TransactionKind::TransferIn),
+      "transfer_out" => Ok(TransactionKind::TransferOut),
+      "account_closed" => Ok(TransactionKind::AccountClosed),
+      _ => Err(FromSqlError::InvalidType),
+    }
+  }
+}
+
 pub struct SQLiteDb {
   connection: rusqlite::Connection,
 }
 
-fn get_connection_impl() -> SQLiteDataBaseResult<rusqlite::Connection> {
-  rusqlite::Connection::open("clients.db")
+struct Migration {
+  description: &'static str,
+  sql: &'static str,
+}
+
+/// Schema changes applied in order on top of the tables created by `create_tables`,
+/// migration at index N upgrades the database to `PRAGMA user_version` N + 1.
+/// Each migration runs in its own transaction together with the version bump.
+const MIGRATIONS: &[Migration] = &[
+  // databases with user_version 0 keep balances and amounts in whole units
+  Migration {
+    description: "convert balances to minor units",
+    sql: "
+      UPDATE clients
+      SET balance = balance * 100;
+
+      UPDATE transactions
+      SET amount = amount * 100,
+        balance = balance * 100;
+    ",
+  },
+  Migration {
+    description: "add currency columns",
+    sql: "
+      ALTER TABLE clients
+      ADD COLUMN currency TEXT NOT NULL DEFAULT 'PLN';
+
+      ALTER TABLE transactions
+      ADD COLUMN exchangeRate INTEGER;
+    ",
+  },
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+fn get_connection_impl(path: &Path) -> SQLiteDataBaseResult<rusqlite::Connection> {
+  rusqlite::Connection::open(path)
     .report()
+    .attach_printable_lazy(|| {
+      format!("failed to open database file: {}", path.display())
+    })
     .change_context(SQLiteDatabaseError::ConnectionFailed)
 }
 
 impl SQLiteDb {
-  pub fn new() -> Self {
-    let connection = match get_connection_impl() {
+  pub fn new(path: &Path) -> Self {
+    let connection = match get_connection_impl(path) {
       Err(error) => {
-        println!("\nerror: {:?}", error);
+        eprintln!("\nerror: {:?}", error);
         panic!("SQLiteDb::new() failed");
       },
       Ok(conn) => conn,
@@ -56,14 +193,24 @@ impl SQLiteDb {
       connection
     };
 
-    if let Err(error) = db.create_clients_table() {
-      println!("\nfailed to create clients table, error: {:?}", error);
+    if let Err(error) = db.create_tables() {
+      eprintln!("\nfailed to create database tables, error: {:?}", error);
       panic!("SQLiteDb::new() failed");
     }
 
     db
   }
 
+  fn create_tables(&self) -> SQLiteDataBaseResult<()> {
+    self.check_schema_version()?;
+    self.create_clients_table()?;
+    self.create_transactions_table()?;
+    self.create_login_attempts_table()?;
+    self.migrate()?;
+
+    Ok(())
+  }
+
   fn create_clients_table(&self) -> SQLiteDataBaseResult<()> {
     self.connection.execute(
       "
@@ -86,16 +233,39 @@ impl SQLiteDb {
 
   }
 
-  fn insert_client(&self, client: &Client) -> SQLiteDataBaseResult<()> {
+  fn create_transactions_table(&self) -> SQLiteDataBaseResult<()> {
     self.connection.execute(
       "
-        INSERT INTO clients(cardNumber, pin, balance)
-        VALUES(?1, ?2, ?3)
+        CREATE TABLE IF NOT EXISTS transactions(
+          id INTEGER PRIMARY KEY AUTOINCREMENT,
+          cardNumber TEXT NOT NULL,
+          timestamp INTEGER NOT NULL,
+          kind TEXT NOT NULL,
+          amount INTEGER NOT NULL,
+          counterparty TEXT,
+          balance INTEGER NOT NULL
+        )
+      ",
+      []
+    )
+      .report()
+      .attach_printable("failed to execute CREATE TABLE transactions query")
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    Ok(())
+  }
+
+  fn insert_client(client: &Client, conn: &rusqlite::Connection) -> SQLiteDataBaseResult<()> {
+    conn.execute(
+      "
+        INSERT INTO clients(cardNumber, pin, balance, currency)
+        VALUES(?1, ?2, ?3, ?4)
       ",
       params![
         client.card_number,
         client.pin,
-        client.balance
+        client.balance,
+        client.currency
       ]
     )
       .report()
@@ -131,36 +301,259 @@ impl SQLiteDb {
 
     Ok(())
   }
-}
 
-impl Database for SQLiteDb {
-  fn name(&self) -> &str {
-    "sqlite"
+  fn create_login_attempts_table(&self) -> SQLiteDataBaseResult<()> {
+    self.connection.execute(
+      "
+        CREATE TABLE IF NOT EXISTS loginAttempts(
+          cardNumber TEXT PRIMARY KEY,
+          failed INTEGER NOT NULL,
+          locked INTEGER NOT NULL
+        )
+      ",
+      []
+    )
+      .report()
+      .attach_printable("failed to execute CREATE TABLE loginAttempts query")
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    Ok(())
   }
 
-  fn save_new_client(&mut self, client: Client) -> DatabaseResult<()> {
-    let has_client = self.has_client(&client.card_number)?;
+  fn get_schema_version(&self) -> SQLiteDataBaseResult<u32> {
+    self.connection.query_row("PRAGMA user_version", [], |row| row.get(0))
+      .report()
+      .attach_printable("failed to read user_version")
+      .change_context(SQLiteDatabaseError::QueryFailed)
+  }
 
-    if has_client {
-      return Err(
-        Report::new(
-          SQLiteDatabaseError::ClientAlreadyExists(client)
-        )
-          .change_context(DatabaseError::SQLite)
-      );
+  /// Refuses databases written by a newer version of the application,
+  /// older versions would silently ignore or corrupt the columns they don't know about
+  fn check_schema_version(&self) -> SQLiteDataBaseResult<()> {
+    let schema_version = self.get_schema_version()?;
+
+    if schema_version > SCHEMA_VERSION {
+      return Err(Report::new(SQLiteDatabaseError::UnsupportedSchemaVersion(schema_version)));
+    }
+
+    Ok(())
+  }
+
+  fn migrate(&self) -> SQLiteDataBaseResult<()> {
+    self.apply_migrations(MIGRATIONS)
+  }
+
+  fn apply_migrations(&self, migrations: &[Migration]) -> SQLiteDataBaseResult<()> {
+    for (index, migration) in migrations.iter().enumerate() {
+      self.execute_migration(index as u32 + 1, migration)
+        .attach_printable_lazy(|| {
+          format!("failed to {}", migration.description)
+        })?;
     }
 
-    self.insert_client(&client)
+    Ok(())
+  }
+
+  /// Version is checked inside of the transaction, other process opening the database
+  /// at the same time may have already applied the migration
+  fn execute_migration(&self, version: u32, migration: &Migration) -> SQLiteDataBaseResult<()> {
+    let transaction = rusqlite::Transaction::new_unchecked(&self.connection, TransactionBehavior::Immediate)
+      .report()
+      .attach_printable("failed to create transaction")
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    if self.get_schema_version()? >= version {
+      return Ok(());
+    }
+
+    transaction.execute_batch(migration.sql)
+      .report()
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    transaction.pragma_update(None, "user_version", version)
+      .report()
       .attach_printable_lazy(|| {
-        format!("failed to insert client to database")
+        format!("failed to set user_version to {version}")
       })
-      .change_context(DatabaseError::SQLite)?;
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    transaction.commit()
+      .report()
+      .attach_printable("failed to commit migration")
+      .change_context(SQLiteDatabaseError::QueryFailed)
+  }
+
+  fn check_funds(funds: Money) -> DatabaseResult<()> {
+    if funds.is_positive() {
+      return Ok(());
+    }
+
+    Err(Report::new(SQLiteDatabaseError::InvalidAmount))
+      .attach_printable_lazy(|| {
+        format!("funds: {}", funds)
+      })
+      .change_context(DatabaseError::SQLite)
+  }
+
+  fn check_different_cards(sender_card_number: &str, receiver_card_number: &str) -> DatabaseResult<()> {
+    if sender_card_number != receiver_card_number {
+      return Ok(());
+    }
+
+    Err(Report::new(SQLiteDatabaseError::TransferToSameCard))
+      .attach_printable_lazy(|| {
+        format!("card_number: {}", sender_card_number)
+      })
+      .change_context(DatabaseError::SQLite)
+  }
+
+  fn add_to_balance(client: &mut Client, funds: Money) -> DatabaseResult<()> {
+    match client.balance.checked_add(funds) {
+      None => Err(Report::new(SQLiteDatabaseError::BalanceOverflow))
+        .attach_printable_lazy(|| {
+          format!(
+            "balance of card_number: {} is {}, added funds: {}",
+            client.card_number,
+            client.balance,
+            funds
+          )
+        })
+        .change_context(DatabaseError::SQLite),
+      Some(balance) => {
+        client.balance = balance;
+        Ok(())
+      },
+    }
+  }
+
+  fn ensure_client_exists(card_number: &str, conn: &rusqlite::Connection) -> DatabaseResult<()> {
+    if SQLiteDb::select_has_client(card_number, conn)? {
+      return Ok(());
+    }
+
+    Err(Report::new(SQLiteDatabaseError::ClientNotFound))
+      .attach_printable_lazy(|| {
+        format!("client with card_number: {} not found", card_number)
+      })
+      .change_context(DatabaseError::SQLite)
+  }
+
+  fn delete_login_attempts(card_number: &str, conn: &rusqlite::Connection) -> SQLiteDataBaseResult<()> {
+    conn.execute(
+      "
+        DELETE FROM loginAttempts
+        WHERE cardNumber = ?
+      ",
+      [&card_number]
+    )
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to delete login attempts of card_number: {}", card_number)
+      })
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
 
     Ok(())
   }
 
-  fn has_client(&self, card_number: &str) -> DatabaseResult<bool> {
-    let mut stmt = self.connection.prepare(
+  fn insert_transaction(conn: &rusqlite::Connection, transaction: &Transaction) -> SQLiteDataBaseResult<()> {
+    conn.execute(
+      "
+        INSERT INTO transactions(
+          cardNumber, timestamp, kind, amount, counterparty, balance, exchangeRate
+        )
+        VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)
+      ",
+      params![
+        transaction.card_number,
+        transaction.timestamp,
+        transaction.kind,
+        transaction.amount,
+        transaction.counterparty,
+        transaction.balance,
+        transaction.exchange_rate
+      ]
+    )
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to insert transaction: {:?}", transaction)
+      })
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    Ok(())
+  }
+
+  /// Expects columns cardNumber, pin, balance, currency
+  fn client_from_row(row: &rusqlite::Row) -> rusqlite::Result<Client> {
+    Ok(Client {
+      card_number: row.get(0)?,
+      pin: row.get(1)?,
+      balance: row.get(2)?,
+      currency: row.get(3)?,
+    })
+  }
+
+  /// Expects columns id, timestamp, cardNumber, kind, amount, counterparty, balance, exchangeRate
+  fn transaction_from_row(row: &rusqlite::Row) -> rusqlite::Result<Transaction> {
+    Ok(Transaction {
+      id: row.get(0)?,
+      timestamp: row.get(1)?,
+      card_number: row.get(2)?,
+      kind: row.get(3)?,
+      amount: row.get(4)?,
+      counterparty: row.get(5)?,
+      balance: row.get(6)?,
+      exchange_rate: row.get(7)?,
+    })
+  }
+
+  fn query_all<T>(
+    &self,
+    sql: &str,
+    map_row: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>
+  ) -> SQLiteDataBaseResult<Vec<T>> {
+    let mut stmt = self.connection.prepare(sql)
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to prepare query: {sql}")
+      })
+      .change_context(SQLiteDatabaseError::PrepareQueryFailed)?;
+
+    stmt.query_map([], map_row)
+      .and_then(|rows| rows.collect::<rusqlite::Result<Vec<T>>>())
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to execute query: {sql}")
+      })
+      .change_context(SQLiteDatabaseError::QueryFailed)
+  }
+
+  fn insert_login_attempts(
+    card_number: &str,
+    attempts: &LoginAttempts,
+    conn: &rusqlite::Connection
+  ) -> SQLiteDataBaseResult<()> {
+    conn.execute(
+      "
+        INSERT INTO loginAttempts(cardNumber, failed, locked)
+        VALUES(?1, ?2, ?3)
+      ",
+      params![
+        card_number,
+        attempts.failed,
+        attempts.locked
+      ]
+    )
+      .report()
+      .attach_printable_lazy(|| {
+        format!("failed to insert login attempts of card_number: {}", card_number)
+      })
+      .change_context(SQLiteDatabaseError::QueryFailed)?;
+
+    Ok(())
+  }
+
+  fn select_has_client(card_number: &str, conn: &rusqlite::Connection) -> DatabaseResult<bool> {
+    let mut stmt = conn.prepare(
       "
         SELECT * FROM clients
         WHERE cardNumber = ?
@@ -191,10 +584,10 @@ impl Database for SQLiteDb {
     }
   }
 
-  fn get_client(&self, card_number: &str) -> DatabaseResult<Client> {
-    let mut stmt = self.connection.prepare(
+  fn select_client(card_number: &str, conn: &rusqlite::Connection) -> DatabaseResult<Client> {
+    let mut stmt = conn.prepare(
       "
-        SELECT cardNumber, pin, balance
+        SELECT cardNumber, pin, balance, currency
         FROM clients
         WHERE cardNumber = ?
       "
@@ -209,13 +602,8 @@ impl Database for SQLiteDb {
       .change_context(SQLiteDatabaseError::PrepareQueryFailed)
       .change_context(DatabaseError::SQLite)?;
 
-    let client: Client = stmt.query_row([&card_number], |row| {
-      Ok(Client {
-        card_number: row.get(0)?,
-        pin: row.get(1)?,
-        balance: row.get(2)?
-      })
-    })
+    let client: Option<Client> = stmt.query_row([&card_number], SQLiteDb::client_from_row)
+      .optional()
       .report()
       .attach_printable_lazy(|| {
         format!(
@@ -226,13 +614,108 @@ impl Database for SQLiteDb {
       .change_context(SQLiteDatabaseError::QueryFailed)
       .change_context(DatabaseError::SQLite)?;
 
-    Ok(client)
+    client
+      .ok_or_else(|| Report::new(SQLiteDatabaseError::ClientNotFound))
+      .attach_printable_lazy(|| {
+        format!("client with card_number: {} not found", card_number)
+      })
+      .change_context(DatabaseError::SQLite)
+  }
+
+  fn select_login_attempts(card_number: &str, conn: &rusqlite::Connection) -> DatabaseResult<LoginAttempts> {
+    let mut stmt = conn.prepare(
+      "
+        SELECT failed, locked
+        FROM loginAttempts
+        WHERE cardNumber = ?
+      "
+    )
+      .report()
+      .attach_printable_lazy(|| {
+        format!(
+          "failed to prepare select login attempts query with card_number: {}",
+          card_number
+        )
+      })
+      .change_context(SQLiteDatabaseError::PrepareQueryFailed)
+      .change_context(DatabaseError::SQLite)?;
+
+    let attempts = stmt.query_row([&card_number], |row| {
+      Ok(LoginAttempts {
+        failed: row.get(0)?,
+        locked: row.get(1)?,
+      })
+    });
+
+    match attempts {
+      Err(rusqlite::Error::QueryReturnedNoRows) => Ok(LoginAttempts::default()),
+      Err(error) => Err(error)
+        .report()
+        .attach_printable_lazy(|| {
+          format!("failed to get login attempts of card_number: {}", card_number)
+        })
+        .change_context(SQLiteDatabaseError::QueryFailed)
+        .change_context(DatabaseError::SQLite),
+      Ok(attempts) => Ok(attempts),
+    }
+  }
+
+  /// Takes the write lock at the beginning, so balances read in the transaction
+  /// can't be changed by other connections before the transaction commits
+  fn immediate_transaction(&mut self) -> DatabaseResult<rusqlite::Transaction<'_>> {
+    self.connection.transaction_with_behavior(TransactionBehavior::Immediate)
+      .report()
+      .attach_printable("failed to create transaction")
+      .change_context(SQLiteDatabaseError::QueryFailed)
+      .change_context(DatabaseError::SQLite)
+  }
+
+  fn commit(transaction: rusqlite::Transaction) -> DatabaseResult<()> {
+    transaction.commit()
+      .report()
+      .attach_printable("failed to commit transaction")
+      .change_context(DatabaseError::SQLite)
+  }
+}
+
+impl Database for SQLiteDb {
+  fn name(&self) -> &str {
+    "sqlite"
+  }
+
+  fn save_new_client(&mut self, client: Client) -> DatabaseResult<()> {
+    let transaction = self.immediate_transaction()?;
+
+    if SQLiteDb::select_has_client(&client.card_number, &transaction)? {
+      return Err(
+        Report::new(
+          SQLiteDatabaseError::ClientAlreadyExists(client)
+        )
+          .change_context(DatabaseError::SQLite)
+      );
+    }
+
+    SQLiteDb::insert_client(&client, &transaction)
+      .attach_printable("failed to insert client to database")
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::commit(transaction)
+  }
+
+  fn has_client(&self, card_number: &str) -> DatabaseResult<bool> {
+    SQLiteDb::select_has_client(card_number, &self.connection)
+  }
+
+  fn get_client(&self, card_number: &str) -> DatabaseResult<Client> {
+    SQLiteDb::select_client(card_number, &self.connection)
   }
 
   fn remove_client(&mut self, card_number: &str) -> DatabaseResult<Client> {
-    let client = self.get_client(card_number)?;
+    let transaction = self.immediate_transaction()?;
 
-    self.connection.execute(
+    let client = SQLiteDb::select_client(card_number, &transaction)?;
+
+    transaction.execute(
       "
         DELETE FROM clients
         WHERE cardNumber = ?
@@ -249,44 +732,90 @@ impl Database for SQLiteDb {
       .change_context(SQLiteDatabaseError::QueryFailed)
       .change_context(DatabaseError::SQLite)?;
 
+    SQLiteDb::delete_login_attempts(card_number, &transaction)
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::insert_transaction(
+      &transaction,
+      &Transaction::new(card_number, TransactionKind::AccountClosed, client.balance, Money::ZERO)
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::commit(transaction)?;
+
     Ok(client)
   }
 
-  fn add_funds(&mut self, funds: u32, card_number: &str) -> DatabaseResult<()> {
-    let mut client = self.get_client(card_number)?;
+  fn add_funds(&mut self, funds: Money, card_number: &str) -> DatabaseResult<()> {
+    SQLiteDb::check_funds(funds)?;
 
-    client.balance += funds as i32;
+    let transaction = self.immediate_transaction()?;
 
-    self.connection.execute(
+    let mut client = SQLiteDb::select_client(card_number, &transaction)?;
+
+    SQLiteDb::add_to_balance(&mut client, funds)?;
+
+    SQLiteDb::update_client_balance(&client, &transaction)
+      .attach_printable_lazy(|| {
+        format!(
+          "failed to update client balance, client card_number: {}",
+          card_number
+        )
+      })
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::insert_transaction(
+      &transaction,
+      &Transaction::new(card_number, TransactionKind::Deposit, funds, client.balance)
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::commit(transaction)
+  }
+
+  fn update_pin(&mut self, card_number: &str, pin: &str) -> DatabaseResult<()> {
+    let updated = self.connection.execute(
       "
         UPDATE clients
-        SET balance = ?1
+        SET pin = ?1
         WHERE cardNumber = ?2
       ",
       params![
-        client.balance,
-        client.card_number,
+        pin,
+        card_number
       ]
     )
       .report()
       .attach_printable_lazy(|| {
-        format!(
-          "failed to update client balance, client card_number: {}",
-          card_number
-        )
+        format!("failed to update PIN of client with card_number: {}", card_number)
       })
       .change_context(SQLiteDatabaseError::QueryFailed)
       .change_context(DatabaseError::SQLite)?;
 
+    if updated == 0 {
+      return Err(Report::new(SQLiteDatabaseError::ClientNotFound))
+        .attach_printable_lazy(|| {
+          format!("client with card_number: {} not found", card_number)
+        })
+        .change_context(DatabaseError::SQLite)
+    }
+
     Ok(())
   }
 
   fn transfer_funds(
-    &mut self, funds: u32,
+    &mut self,
+    funds: Money,
     sender_card_number: &str,
-    receiver_card_number: &str
+    receiver_card_number: &str,
+    rates: &ExchangeRates
   ) -> DatabaseResult<()> {
-    let mut sender_client = self.get_client(sender_card_number)
+    SQLiteDb::check_funds(funds)?;
+    SQLiteDb::check_different_cards(sender_card_number, receiver_card_number)?;
+
+    let transaction = self.immediate_transaction()?;
+
+    let mut sender_client = SQLiteDb::select_client(sender_card_number, &transaction)
       .attach_printable_lazy(|| {
         format!(
           "sender client not found, sender_card_number: {}",
@@ -294,7 +823,7 @@ impl Database for SQLiteDb {
         )
       })?;
 
-    let mut receiver_client = self.get_client(receiver_card_number)
+    let mut receiver_client = SQLiteDb::select_client(receiver_card_number, &transaction)
       .attach_printable_lazy(|| {
         format!(
           "receiver client not found, receiver_card_number: {}",
@@ -302,50 +831,95 @@ impl Database for SQLiteDb {
         )
       })?;
 
-    let sender_original_balance = sender_client.balance;
-    sender_client.balance -= funds as i32;
-
-    if sender_client.balance < 0 {
-      return Err(Report::new(DatabaseError::SQLite))
+    sender_client.balance = match sender_client.balance.checked_sub(funds) {
+      Some(balance) if !balance.is_negative() => balance,
+      _ => return Err(Report::new(SQLiteDatabaseError::InsufficientFunds))
         .attach_printable_lazy(|| {
           format!(
-            "sender's balance before transfer: {}, after transfer: {}",
-            sender_original_balance,
-            sender_client.balance
+            "sender's balance before transfer: {}, transferred funds: {}",
+            sender_client.balance,
+            funds
           )
         })
-    }
-
-    receiver_client.balance += funds as i32;
+        .change_context(DatabaseError::SQLite),
+    };
 
-    let transaction = self.connection.transaction()
-      .report()
-      .attach_printable_lazy(|| {
-        format!("failed to create transaction")
-      })
+    let (received_funds, exchange_rate) = rates.convert(funds, sender_client.currency, receiver_client.currency)
+      .change_context(SQLiteDatabaseError::CurrencyConversionFailed)
       .change_context(DatabaseError::SQLite)?;
 
+    SQLiteDb::add_to_balance(&mut receiver_client, received_funds)?;
+
     SQLiteDb::update_client_balance(&sender_client, &transaction)
-      .attach_printable_lazy(|| {
-        format!("failed to update sender_client in database")
-      })
+      .attach_printable("failed to update sender_client in database")
       .change_context(DatabaseError::SQLite)?;
 
     SQLiteDb::update_client_balance(&receiver_client, &transaction)
+      .attach_printable("failed to update receiver_client in database")
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::insert_transaction(
+      &transaction,
+      &Transaction::new(sender_card_number, TransactionKind::TransferOut, funds, sender_client.balance)
+        .with_counterparty(receiver_card_number, exchange_rate)
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::insert_transaction(
+      &transaction,
+      &Transaction::new(receiver_card_number, TransactionKind::TransferIn, received_funds, receiver_client.balance)
+        .with_counterparty(sender_card_number, exchange_rate)
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::commit(transaction)
+  }
+
+  fn get_login_attempts(&self, card_number: &str) -> DatabaseResult<LoginAttempts> {
+    SQLiteDb::select_login_attempts(card_number, &self.connection)
+  }
+
+  fn register_failed_login(&mut self, card_number: &str, max_attempts: u32) -> DatabaseResult<LoginAttempts> {
+    let transaction = self.immediate_transaction()?;
+
+    SQLiteDb::ensure_client_exists(card_number, &transaction)?;
+
+    transaction.execute(
+      "
+        INSERT INTO loginAttempts(cardNumber, failed, locked)
+        VALUES(?1, 1, 1 >= ?2)
+        ON CONFLICT(cardNumber) DO UPDATE
+        SET failed = failed + 1,
+          locked = locked OR failed + 1 >= ?2
+      ",
+      params![
+        card_number,
+        max_attempts
+      ]
+    )
+      .report()
       .attach_printable_lazy(|| {
-        format!("failed to update receiver_client in database")
+        format!("failed to save failed login of card_number: {}", card_number)
       })
+      .change_context(SQLiteDatabaseError::QueryFailed)
       .change_context(DatabaseError::SQLite)?;
 
-    return match transaction.commit() {
-      Err(error) => Err(error)
-        .report()
-        .attach_printable_lazy(|| {
-          format!("failed to commit transfer transaction")
-        })
-        .change_context(DatabaseError::SQLite),
-      Ok(()) => Ok(())
-    }
+    let attempts = SQLiteDb::select_login_attempts(card_number, &transaction)?;
+
+    SQLiteDb::commit(transaction)?;
+
+    Ok(attempts)
+  }
+
+  fn reset_login_attempts(&mut self, card_number: &str) -> DatabaseResult<()> {
+    let transaction = self.immediate_transaction()?;
+
+    SQLiteDb::ensure_client_exists(card_number, &transaction)?;
+
+    SQLiteDb::delete_login_attempts(card_number, &transaction)
+      .change_context(DatabaseError::SQLite)?;
+
+    SQLiteDb::commit(transaction)
   }
 
   fn get_clients_count(&self) -> DatabaseResult<u32> {
@@ -372,6 +946,109 @@ impl Database for SQLiteDb {
 
     Ok(count)
   }
+
+  fn get_history(&self, card_number: &str) -> DatabaseResult<Vec<Transaction>> {
+    let mut stmt = self.connection.prepare(
+      "
+        SELECT id, timestamp, cardNumber, kind, amount, counterparty, balance, exchangeRate
+        FROM transactions
+        WHERE cardNumber = ?
+        ORDER BY id
+      "
+    )
+      .report()
+      .attach_printable_lazy(|| {
+        format!(
+          "failed to prepare select transactions query with card_number: {}",
+          card_number
+        )
+      })
+      .change_context(SQLiteDatabaseError::PrepareQueryFailed)
+      .change_context(DatabaseError::SQLite)?;
+
+    let history = stmt.query_map([&card_number], SQLiteDb::transaction_from_row)
+      .and_then(|rows| rows.collect::<rusqlite::Result<Vec<Transaction>>>())
+      .report()
+      .attach_printable_lazy(|| {
+        format!(
+          "failed to get transactions of card_number: {} from database",
+          card_number
+        )
+      })
+      .change_context(SQLiteDatabaseError::QueryFailed)
+      .change_context(DatabaseError::SQLite)?;
+
+    Ok(history)
+  }
+
+  fn export(&self) -> DatabaseResult<Snapshot> {
+    let clients = self.query_all(
+      "
+        SELECT cardNumber, pin, balance, currency
+        FROM clients
+        ORDER BY cardNumber
+      ",
+      SQLiteDb::client_from_row
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    let login_attempts = self.query_all(
+      "
+        SELECT cardNumber, failed, locked
+        FROM loginAttempts
+      ",
+      |row| Ok((row.get(0)?, LoginAttempts { failed: row.get(1)?, locked: row.get(2)? }))
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    let transactions = self.query_all(
+      "
+        SELECT id, timestamp, cardNumber, kind, amount, counterparty, balance, exchangeRate
+        FROM transactions
+        ORDER BY id
+      ",
+      SQLiteDb::transaction_from_row
+    )
+      .change_context(DatabaseError::SQLite)?;
+
+    Ok(Snapshot {
+      clients,
+      login_attempts: login_attempts.into_iter().collect(),
+      transactions,
+    })
+  }
+
+  fn import(&mut self, snapshot: Snapshot) -> DatabaseResult<()> {
+    let transaction = self.immediate_transaction()?;
+
+    for client in &snapshot.clients {
+      if SQLiteDb::select_has_client(&client.card_number, &transaction)? {
+        return Err(
+          Report::new(
+            SQLiteDatabaseError::ClientAlreadyExists(client.clone())
+          )
+            .change_context(DatabaseError::SQLite)
+        );
+      }
+    }
+
+    for client in &snapshot.clients {
+      SQLiteDb::insert_client(client, &transaction)
+        .change_context(DatabaseError::SQLite)?;
+    }
+
+    for (card_number, attempts) in &snapshot.login_attempts {
+      SQLiteDb::insert_login_attempts(card_number, attempts, &transaction)
+        .change_context(DatabaseError::SQLite)?;
+    }
+
+    for ledger_entry in &snapshot.transactions {
+      SQLiteDb::insert_transaction(&transaction, ledger_entry)
+        .change_context(DatabaseError::SQLite)?;
+    }
+
+    SQLiteDb::commit(transaction)
+  }
 }
 
 #[cfg(test)]
@@ -383,7 +1060,7 @@ pub mod tests {
       connection: get_mock_connection(),
     };
 
-    sqlite_db.create_clients_table().unwrap();
+    sqlite_db.create_tables().unwrap();
 
     sqlite_db
   }
@@ -404,6 +1081,189 @@ pub mod tests {
     );
   }
 
+  #[test]
+  fn should_record_history() {
+    let client_mock = crate::database::tests::get_mock_client();
+    let mut receiver_mock = crate::database::tests::get_mock_client();
+    receiver_mock.card_number = String::from("4000000000000001");
+
+    let card_number = client_mock.card_number.clone();
+    let receiver_card_number = receiver_mock.card_number.clone();
+
+    let mut sql_db = get_mock_db();
+
+    sql_db.save_new_client(client_mock).unwrap();
+    sql_db.save_new_client(receiver_mock).unwrap();
+    sql_db.add_funds(Money::from_minor_units(500), &card_number).unwrap();
+    sql_db.transfer_funds(
+      Money::from_minor_units(200),
+      &card_number,
+      &receiver_card_number,
+      &ExchangeRates::default()
+    ).unwrap();
+    sql_db.remove_client(&receiver_card_number).unwrap();
+
+    let history = sql_db.get_history(&card_number).unwrap();
+    let kinds: Vec<TransactionKind> = history.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, [TransactionKind::Deposit, TransactionKind::TransferOut]);
+    assert_eq!(history[1].amount, Money::from_minor_units(200));
+    assert_eq!(history[1].balance, Money::from_minor_units(300));
+    assert_eq!(history[1].counterparty.as_deref(), Some(receiver_card_number.as_str()));
+
+    let receiver_history = sql_db.get_history(&receiver_card_number).unwrap();
+    let kinds: Vec<TransactionKind> = receiver_history.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, [TransactionKind::TransferIn, TransactionKind::AccountClosed]);
+    assert_eq!(receiver_history[1].amount, Money::from_minor_units(200));
+    assert_eq!(receiver_history[1].balance, Money::ZERO);
+  }
+
+  #[test]
+  fn should_store_database_in_given_file() {
+    let path = std::env::temp_dir()
+      .join(format!("rust-bank-sqlite-{}", std::process::id()))
+      .join("bank.db");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+    let mut sql_db = SQLiteDb::new(&path);
+    sql_db.save_new_client(crate::database::tests::get_mock_client()).unwrap();
+    drop(sql_db);
+
+    let reopened_db = SQLiteDb::new(&path);
+    assert_eq!(reopened_db.get_clients_count().unwrap(), 1);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn should_conserve_total_balance_in_concurrent_transfers() {
+    let dir = std::env::temp_dir().join(format!("rust-bank-sqlite-concurrent-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bank.db");
+
+    let card_numbers: Vec<String> = (0..4).map(|i| format!("400000000000000{i}")).collect();
+
+    let mut sql_db = SQLiteDb::new(&path);
+    for card_number in &card_numbers {
+      let mut client = crate::database::tests::get_mock_client();
+      client.card_number = card_number.clone();
+      sql_db.save_new_client(client).unwrap();
+      sql_db.add_funds(Money::from_minor_units(1000), card_number).unwrap();
+    }
+
+    let threads: Vec<_> = (0..4)
+      .map(|thread| {
+        let path = path.clone();
+        let card_numbers = card_numbers.clone();
+
+        std::thread::spawn(move || {
+          let mut sql_db = SQLiteDb::new(&path);
+          let rates = ExchangeRates::default();
+
+          for i in 0..50 {
+            let sender = &card_numbers[(thread + i) % 4];
+            let receiver = &card_numbers[(thread + i + 1) % 4];
+            let funds = Money::from_minor_units((i as i64 % 7 + 1) * 100);
+
+            if let Err(report) = sql_db.transfer_funds(funds, sender, receiver, &rates) {
+              assert_eq!(ErrorKind::of(&report), ErrorKind::InsufficientFunds, "{report:?}");
+            }
+          }
+        })
+      })
+      .collect();
+
+    for thread in threads {
+      thread.join().unwrap();
+    }
+
+    let mut total = Money::ZERO;
+
+    for card_number in &card_numbers {
+      let balance = sql_db.get_client(card_number).unwrap().balance;
+      let history = sql_db.get_history(card_number).unwrap();
+
+      assert!(!balance.is_negative());
+      assert_eq!(history.last().unwrap().balance, balance, "card_number: {card_number}");
+
+      total = total.checked_add(balance).unwrap();
+    }
+
+    assert_eq!(total, Money::from_minor_units(4000));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn should_migrate_balances_to_minor_units() {
+    let connection = get_mock_connection();
+    connection.execute_batch(
+      "
+        CREATE TABLE clients(
+          id INTEGER PRIMARY KEY,
+          cardNumber TEXT UNIQUE,
+          pin TEXT,
+          balance INTEGER
+        );
+        INSERT INTO clients(cardNumber, pin, balance)
+        VALUES('4000000000000000', '1234', 12);
+      "
+    ).unwrap();
+
+    let sql_db = SQLiteDb { connection };
+    sql_db.create_tables().unwrap();
+    sql_db.create_tables().unwrap();
+
+    let client = sql_db.get_client("4000000000000000").unwrap();
+    assert_eq!(client.balance, Money::from_minor_units(1200));
+  }
+
+  #[test]
+  fn should_set_latest_schema_version() {
+    let sql_db = get_mock_db();
+
+    assert_eq!(sql_db.get_schema_version().unwrap(), SCHEMA_VERSION);
+  }
+
+  #[test]
+  fn should_refuse_newer_schema_version() {
+    let connection = get_mock_connection();
+    connection.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+
+    let sql_db = SQLiteDb { connection };
+    let report = sql_db.create_tables().unwrap_err();
+
+    assert!(matches!(
+      report.current_context(),
+      SQLiteDatabaseError::UnsupportedSchemaVersion(version) if *version == SCHEMA_VERSION + 1
+    ));
+  }
+
+  #[test]
+  fn should_roll_back_failed_migration() {
+    let sql_db = SQLiteDb { connection: get_mock_connection() };
+
+    let migrations = [
+      Migration {
+        description: "create first table",
+        sql: "CREATE TABLE first(id INTEGER);",
+      },
+      Migration {
+        description: "create second table",
+        sql: "CREATE TABLE second(id INTEGER); INSERT INTO missing VALUES(1);",
+      },
+    ];
+
+    assert!(sql_db.apply_migrations(&migrations).is_err());
+    assert_eq!(sql_db.get_schema_version().unwrap(), 1);
+
+    let tables: u32 = sql_db.connection.query_row(
+      "SELECT COUNT(*) FROM sqlite_master WHERE name IN ('first', 'second')",
+      [],
+      |row| row.get(0)
+    ).unwrap();
+    assert_eq!(tables, 1);
+  }
+
   fn get_mock_connection() -> rusqlite::Connection {
     rusqlite::Connection::open_in_memory().unwrap()
   }
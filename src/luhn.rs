@@ -1,46 +1,190 @@
-pub fn is_valid_card_number(digits_str: &str) -> bool {
-  let mut alt = false;
+use error_stack::{Context, Report, Result, ResultExt};
 
-  let digits: Vec<i32> = digits_str
-    .chars()
-    .map(|character| {
-      match character {
-        '0' => 0,
-        '1' => 1,
-        '2' => 2,
-        '3' => 3,
-        '4' => 4,
-        '5' => 5,
-        '6' => 6,
-        '7' => 7,
-        '8' => 8,
-        '9' => 9,
-        _ => panic!("invalid character")
-      }
-    })
-    .collect();
-
-  let mut sum: i32 = 0;
-  let mut i = (digits.len() - 1) as i32;
-  while i >= 0 {
-    let mut temp = digits[i as usize];
-    if alt {
-      temp *= 2;
-      if temp > 9 {
-        temp -= 9; // same as add to digits eg. 1+6 = 7, 16-9 = 7
-      }
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CardNumberError {
+  Empty,
+  /// `position` counts characters of the input from 1
+  InvalidCharacter { position: usize, character: char },
+  InvalidLength(usize),
+  InvalidChecksum,
+}
+
+impl fmt::Display for CardNumberError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      CardNumberError::Empty => write!(f, "card number is empty"),
+      CardNumberError::InvalidCharacter { position, character } => {
+        write!(f, "card number contains invalid character {character:?} at position {position}")
+      },
+      CardNumberError::InvalidLength(length) => write!(
+        f,
+        "card number has {length} digits, expected {} to {}",
+        CARD_NUMBER_LENGTHS.start(),
+        CARD_NUMBER_LENGTHS.end()
+      ),
+      CardNumberError::InvalidChecksum => write!(f, "card number has invalid check digit"),
+    }
+  }
+}
+
+impl Context for CardNumberError {}
+
+pub const CARD_NUMBER_LENGTHS: RangeInclusive<usize> = 12..=19;
+
+/// Separators allowed between groups of digits, e.g. "4000 0001 3008 5832"
+const SEPARATORS: [char; 2] = [' ', '-'];
+
+/// Checks card number entered by user and returns its digits without separators
+pub fn validate_card_number(card_number: &str) -> Result<String, CardNumberError> {
+  let mut digits = Vec::with_capacity(card_number.len());
+
+  for (i, character) in card_number.chars().enumerate() {
+    if SEPARATORS.contains(&character) {
+      continue;
     }
-    sum += temp;
-    alt = !alt;
-    i -= 1;
+
+    let digit = character.to_digit(10)
+      .ok_or_else(|| Report::new(CardNumberError::InvalidCharacter { position: i + 1, character }))?;
+
+    digits.push(digit);
+  }
+
+  if digits.is_empty() {
+    return Err(Report::new(CardNumberError::Empty));
+  }
+
+  if !CARD_NUMBER_LENGTHS.contains(&digits.len()) {
+    return Err(Report::new(CardNumberError::InvalidLength(digits.len())));
+  }
+
+  if !luhn_sum(&digits).is_multiple_of(10) {
+    return Err(Report::new(CardNumberError::InvalidChecksum))
+      .attach_printable_lazy(|| format!("expected check digit: {}", check_digit(&digits[..digits.len() - 1])));
   }
 
-  return sum % 10 == 0;
+  Ok(digits.iter().map(|digit| char::from_digit(*digit, 10).unwrap()).collect())
+}
+
+/// Sum of digits, where every second digit counting from the last one is doubled
+fn luhn_sum(digits: &[u32]) -> u32 {
+  digits
+    .iter()
+    .rev()
+    .enumerate()
+    .map(|(i, digit)| {
+      match i % 2 {
+        1 if *digit > 4 => digit * 2 - 9, // same as add digits eg. 1+6 = 7, 16-9 = 7
+        1 => digit * 2,
+        _ => *digit,
+      }
+    })
+    .sum()
+}
+
+fn check_digit(payload: &[u32]) -> u32 {
+  // check digit will be the last one, so it is computed as if it was 0
+  let digits: Vec<u32> = payload.iter().copied().chain([0]).collect();
+
+  (10 - luhn_sum(&digits) % 10) % 10
 }
 
+/// Digit which appended to `digits_str` makes it pass `validate_card_number`,
+/// unlike user input `digits_str` can't contain separators
+pub fn compute_check_digit(digits_str: &str) -> Result<u32, CardNumberError> {
+  let payload = digits_str
+    .chars()
+    .enumerate()
+    .map(|(i, character)| {
+      character.to_digit(10)
+        .ok_or_else(|| Report::new(CardNumberError::InvalidCharacter { position: i + 1, character }))
+    })
+    .collect::<Result<Vec<u32>, CardNumberError>>()?;
+
+  Ok(check_digit(&payload))
+}
+
+pub fn append_check_digit(digits_str: &str) -> Result<String, CardNumberError> {
+  let check_digit = compute_check_digit(digits_str)?;
+
+  Ok(format!("{digits_str}{check_digit}"))
+}
+
+#[cfg(test)]
 mod tests {
+  use super::*;
+
   #[test]
-  fn should_return_true() {
-    assert_eq!(crate::luhn::is_valid_card_number("4000000130085832"), true)
+  fn should_accept_valid_card_numbers() {
+    let cases = [
+      "4000000130085832",
+      "4000 0001 3008 5832",
+      "4000-0001-3008-5832",
+      " 4000 - 0001 3008-5832 ",
+    ];
+
+    for card_number in cases {
+      assert_eq!(validate_card_number(card_number).unwrap(), "4000000130085832", "{card_number:?}");
+    }
+  }
+
+  #[test]
+  fn should_reject_invalid_card_numbers() {
+    let cases = [
+      ("", CardNumberError::Empty),
+      (" - ", CardNumberError::Empty),
+      ("4000000130085833", CardNumberError::InvalidChecksum),
+      ("4000 0001 3008 583a", CardNumberError::InvalidCharacter { position: 19, character: 'a' }),
+      ("4000\u{00e9}", CardNumberError::InvalidCharacter { position: 5, character: '\u{00e9}' }),
+      ("４０００000130085832", CardNumberError::InvalidCharacter { position: 1, character: '４' }),
+      ("+4000000130085832", CardNumberError::InvalidCharacter { position: 1, character: '+' }),
+      ("0", CardNumberError::InvalidLength(1)),
+      // valid checksum, but too short for a card number
+      ("79927398713", CardNumberError::InvalidLength(11)),
+      ("40000001300858320000", CardNumberError::InvalidLength(20)),
+    ];
+
+    for (card_number, error) in cases {
+      let report = validate_card_number(card_number).unwrap_err();
+      assert_eq!(report.current_context(), &error, "{card_number:?}");
+    }
+  }
+
+  #[test]
+  fn should_compute_check_digit() {
+    assert_eq!(compute_check_digit("400000013008583").unwrap(), 2);
+    assert_eq!(compute_check_digit("7992739871").unwrap(), 3);
+    assert_eq!(compute_check_digit("").unwrap(), 0);
+    assert_eq!(append_check_digit("400000013008583").unwrap(), "4000000130085832");
+
+    let report = append_check_digit("4000 0001").unwrap_err();
+    assert_eq!(report.current_context(), &CardNumberError::InvalidCharacter { position: 5, character: ' ' });
+  }
+
+  #[test]
+  fn should_append_only_valid_check_digit() {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::SmallRng;
+
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    for _ in 0..1000 {
+      let length = rng.gen_range(CARD_NUMBER_LENGTHS) - 1;
+      let payload: String = (0..length)
+        .map(|_| char::from_digit(rng.gen_range(0..10), 10).unwrap())
+        .collect();
+
+      let card_number = append_check_digit(&payload).unwrap();
+      assert_eq!(validate_card_number(&card_number).unwrap(), card_number);
+
+      let check_digit = compute_check_digit(&payload).unwrap();
+      for other_digit in (0..10).filter(|digit| *digit != check_digit) {
+        let card_number = format!("{payload}{other_digit}");
+        let report = validate_card_number(&card_number).unwrap_err();
+        assert_eq!(report.current_context(), &CardNumberError::InvalidChecksum, "{card_number}");
+      }
+    }
   }
 }
@@ -2,11 +2,25 @@ mod database;
 mod menu;
 mod command_line;
 mod luhn;
+mod card_number;
+mod pin;
+mod money;
+mod currency;
+mod cli;
+mod output;
+mod server;
 
 use database::*;
 use menu::Menu;
+use currency::ExchangeRates;
+use card_number::CardNumberGenerator;
+use cli::Command;
+use output::OutputFormat;
 
 use clap::{Parser, ValueEnum};
+use error_stack::{Context, IntoReport, Report};
+
+use std::path::{Path, PathBuf};
 
 // Simple sort of banking program
 #[derive(Parser)]
@@ -15,25 +29,150 @@ struct Cli {
   /// Type of database
   #[clap(default_value_t = DataBaseType::JSON, arg_enum, value_parser)]
   database: DataBaseType,
+
+  /// Number of consecutive wrong PINs after which the card gets locked
+  #[clap(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+  max_pin_attempts: u32,
+
+  /// Directory with database and exchange rates files, created when missing
+  #[clap(long, value_name = "DIR", env = "RUST_BANK_DATA_DIR", default_value = ".", value_parser)]
+  data_dir: PathBuf,
+
+  /// Database file, overrides the default file name in the data directory
+  #[clap(long, value_name = "FILE", env = "RUST_BANK_DB_PATH", value_parser)]
+  db_path: Option<PathBuf>,
+
+  /// JSON file with exchange rates used for transfers between currencies [default: <DATA_DIR>/rates.json]
+  #[clap(long, value_name = "FILE", value_parser)]
+  rates: Option<PathBuf>,
+
+  /// Issuer range of new card numbers as FIRST[-LAST]:LENGTH, e.g. 510000-519999:16, repeat for more ranges
+  #[clap(
+    long = "card-range",
+    value_name = "RANGE",
+    env = "RUST_BANK_CARD_RANGES",
+    default_value = "400000:16",
+    use_value_delimiter = true,
+    value_parser
+  )]
+  card_ranges: Vec<String>,
+
+  /// Format of command results
+  #[clap(long, global = true, default_value_t = OutputFormat::Text, arg_enum, value_parser)]
+  output: OutputFormat,
+
+  /// Run single operation and exit instead of starting the interactive menu
+  #[clap(subcommand)]
+  command: Option<Command>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[allow(clippy::upper_case_acronyms)]
 enum DataBaseType {
   JSON,
   SQLITE,
+  /// Kept only in memory, optionally seeded from data.json formatted file given by --db-path
+  MEMORY,
 }
 
 fn main() {
-  let Cli { database } = Cli::parse();
-  let mut db = db_factory(database);
-  let mut main_menu = Menu::new();
+  let Cli { database, max_pin_attempts, data_dir, db_path, rates, card_ranges, output, command } = Cli::parse();
+
+  let command_name = command.as_ref().map_or("menu", Command::name);
+
+  // migrate opens its own databases, so it runs before the one from the options is opened
+  let database_command = match &command {
+    Some(Command::Migrate(args)) => {
+      let result = args.exec(&data_dir, db_factory, output);
+
+      exit_on_error(command_name, output, result);
+      return;
+    },
+    Some(Command::Database(database_command)) => Some(database_command),
+    None => None,
+  };
+
+  let db_path = db_path.unwrap_or_else(|| data_dir.join(database.default_file_name()));
+  let rates = rates.unwrap_or_else(|| data_dir.join("rates.json"));
+
+  if database.creates_file() {
+    if let Err(report) = create_parent_dir(&db_path).report() {
+      exit_on_startup_error(command_name, output, report.attach_printable("creating data directory failed"));
+    }
+  }
+
+  let mut db = db_factory(database, &db_path);
+
+  let rates = match ExchangeRates::load(&rates) {
+    Err(report) => {
+      exit_on_startup_error(command_name, output, report.attach_printable("loading exchange rates failed"));
+    },
+    Ok(rates) => rates,
+  };
+
+  let card_numbers = match CardNumberGenerator::from_ranges(&card_ranges) {
+    Err(report) => {
+      exit_on_startup_error(command_name, output, report.attach_printable("reading card ranges failed"));
+    },
+    Ok(card_numbers) => card_numbers,
+  };
+
+  if let Some(database_command) = database_command {
+    let result = database_command.exec(db.as_mut(), &rates, &card_numbers, max_pin_attempts, output);
+
+    exit_on_error(command_name, output, result);
+    return;
+  }
+
+  let mut main_menu = Menu::new(max_pin_attempts, rates, card_numbers, output);
 
   main_menu.start(db.as_mut());
 }
 
-fn db_factory(database: DataBaseType) -> Box<dyn Database> {
+impl DataBaseType {
+  fn default_file_name(self) -> &'static str {
+    match self {
+      DataBaseType::JSON => "data.json",
+      DataBaseType::SQLITE => "clients.db",
+      DataBaseType::MEMORY => "seed.json",
+    }
+  }
+
+  /// Memory database only reads its seed file
+  fn creates_file(self) -> bool {
+    self != DataBaseType::MEMORY
+  }
+}
+
+fn exit_on_error(command_name: &str, output: OutputFormat, result: cli::CliResult<()>) {
+  if let Err(report) = result {
+    match output {
+      OutputFormat::Text => eprintln!("{report:?}"),
+      OutputFormat::Json => println!("{}", output::error_json(command_name, report.current_context().0, &report)),
+    }
+
+    std::process::exit(report.current_context().exit_code());
+  }
+}
+
+/// Reported like a failed command, so JSON output stays one object per line
+fn exit_on_startup_error<C: Context>(command_name: &str, output: OutputFormat, report: Report<C>) -> ! {
+  output.print_error(command_name, ErrorKind::of(&report), &report, &format!("\n{report:?}"));
+
+  std::process::exit(1);
+}
+
+fn create_parent_dir(path: &Path) -> std::io::Result<()> {
+  match path.parent() {
+    Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir),
+    _ => Ok(()),
+  }
+}
+
+fn db_factory(database: DataBaseType, path: &Path) -> Box<dyn Database> {
   match database {
-    DataBaseType::JSON => Box::new(JsonDb::new()),
-    DataBaseType::SQLITE => Box::new(SQLiteDb::new()),
+    DataBaseType::JSON => Box::new(JsonDb::new(path)),
+    DataBaseType::SQLITE => Box::new(SQLiteDb::new(path)),
+    DataBaseType::MEMORY => Box::new(MemoryDb::new(path)),
   }
 }
@@ -1,12 +1,18 @@
 mod cmd;
 
 use crate::menu::cmd::*;
+
+pub use crate::menu::cmd::{authenticate, create_account, login_error_kind};
 use crate::Database;
 use crate::command_line::read_from_cmd;
+use crate::currency::ExchangeRates;
+use crate::card_number::CardNumberGenerator;
+use crate::output::OutputFormat;
 
 use error_stack::{Context, Result, ResultExt};
 
 use std::fmt;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct MenuError;
@@ -32,33 +38,54 @@ pub enum MenuAction {
 pub struct Menu {
   header: String,
   commands: Vec<Box<dyn Cmd>>,
+  rates: Rc<ExchangeRates>,
+  max_pin_attempts: u32,
+  output: OutputFormat,
   read_from_cmd: Box<dyn Fn() -> MenuResult<String>>,
 }
 
 impl Menu {
-  pub fn new() -> Self {
+  pub fn new(
+    max_pin_attempts: u32,
+    rates: ExchangeRates,
+    card_numbers: CardNumberGenerator,
+    output: OutputFormat
+  ) -> Self {
     Menu {
       header: String::from("Main menu"),
       commands: vec![
-        CreateAccountCmd::new().into(),
-        LoginCmd::new().into(),
+        CreateAccountCmd::new(Rc::new(card_numbers), output).into(),
+        LoginCmd::new(max_pin_attempts, output).into(),
         ExitCmd::new().into(),
       ],
+      rates: Rc::new(rates),
+      max_pin_attempts,
+      output,
       read_from_cmd: Box::new(Menu::prompt_impl),
     }
   }
 
-  fn new_login_menu(card_number: &str) -> Self {
+  fn new_login_menu(
+    card_number: &str,
+    rates: Rc<ExchangeRates>,
+    max_pin_attempts: u32,
+    output: OutputFormat
+  ) -> Self {
     Menu {
       header: String::from("Login menu"),
       commands: vec![
-        BalanceCmd::new(card_number).into(),
-        AddIncomeCmd::new(card_number).into(),
-        DoTransferCmd::new(card_number).into(),
-        CloseAccountCmd::new(card_number).into(),
+        BalanceCmd::new(card_number, output).into(),
+        HistoryCmd::new(card_number, output).into(),
+        AddIncomeCmd::new(card_number, output).into(),
+        DoTransferCmd::new(card_number, rates.clone(), output).into(),
+        ChangePinCmd::new(card_number, max_pin_attempts, output).into(),
+        CloseAccountCmd::new(card_number, output).into(),
         CloseCmd::new().into(),
         ExitCmd::new().into(),
       ],
+      rates,
+      max_pin_attempts,
+      output,
       read_from_cmd: Box::new(Menu::prompt_impl),
     }
   }
@@ -70,7 +97,12 @@ impl Menu {
         MenuAction::Close => return false,
         MenuAction::Render => {},
         MenuAction::RenderLoginMenu(card_number) => {
-          let mut login_menu = Menu::new_login_menu(&card_number);
+          let mut login_menu = Menu::new_login_menu(
+            &card_number,
+            self.rates.clone(),
+            self.max_pin_attempts,
+            self.output
+          );
 
           let exit = login_menu.start(db);
 
@@ -79,25 +111,25 @@ impl Menu {
           }
         },
         MenuAction::UnknownCommand => {
-          Menu::print_separator();
-          println!("|Unknown command|");
+          self.print_separator();
+          self.output.print_text("|Unknown command|");
         }
       }
     }
   }
 
   fn render(&mut self, db: &mut dyn Database) -> MenuAction {
-    Menu::print_separator();
-    println!("{}:", self.header);
+    self.print_separator();
+    self.output.print_text(&format!("{}:", self.header));
 
     for (i, cmd) in self.commands.iter().enumerate() {
-      println!("{} - {}", i, cmd.name());
+      self.output.print_text(&format!("{} - {}", i, cmd.name()));
     }
 
     let read_from_cmd = self.read_from_cmd.as_ref();
     let line = match read_from_cmd() {
       Err(report) => {
-        println!("\n{report:?}");
+        self.output.print_text(&format!("\n{report:?}"));
 
         return MenuAction::Render;
       },
@@ -120,56 +152,21 @@ impl Menu {
       .change_context(MenuError)
   }
 
-  fn print_separator() {
-    println!("------------------------------------------");
+  fn print_separator(&self) {
+    self.output.print_text("------------------------------------------");
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
 
-  mod json_tests {
-    use super::*;
-    use crate::database::json::tests::get_mock_db;
-
-    // TODO make a macro ?
-
-    #[test]
-    fn should_exit_with_true_json() {
-      exit_with_true(get_mock_db());
-    }
-
-    #[test]
-    fn should_close_with_false_json() {
-      close_with_false(get_mock_db());
-    }
-
-    #[test]
-    fn should_create_account_then_exit_json() {
-      create_account_then_exit(get_mock_db())
-    }
-  }
-
-  mod sqlite_tests {
-    use super::*;
-    use crate::database::sqlite::tests::get_mock_db;
-
-    #[test]
-    fn should_exit_with_true_sqlite() {
-      exit_with_true(get_mock_db());
-    }
-
-    #[test]
-    fn should_close_with_false_sqlite() {
-      close_with_false(get_mock_db());
-    }
-
-    #[test]
-    fn should_create_account_then_exit_sqlite() {
-      create_account_then_exit(get_mock_db())
-    }
-  }
+  backend_tests!(
+    exit_with_true,
+    close_with_false,
+    create_account_then_exit,
+  );
 
   fn exit_with_true(mut db: impl Database) {
     let mut menu = Menu {
@@ -177,6 +174,9 @@ mod tests {
       commands: vec![
         ExitCmd::new().into(),
       ],
+      rates: Rc::default(),
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
       read_from_cmd: Box::new(|| {
         Ok(0.to_string())
       }),
@@ -193,6 +193,9 @@ mod tests {
       commands: vec![
         CloseCmd::new().into(),
       ],
+      rates: Rc::default(),
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
       read_from_cmd: Box::new(|| {
         Ok(0.to_string())
       }),
@@ -211,9 +214,12 @@ mod tests {
     let mut menu = Menu {
       header: String::from("Test menu"),
       commands: vec![
-        CreateAccountCmd::new().into(), // 0
+        get_mock_create_account_cmd("").into(), // 0
         ExitCmd::new().into(), // 1
       ],
+      rates: Rc::default(),
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
       read_from_cmd: Box::new(move || {
         let ctr = *menu_read_from_cmd_ctr.borrow();
         menu_read_from_cmd_ctr.replace(ctr + 1);
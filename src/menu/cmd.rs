@@ -6,15 +6,22 @@ mod balance;
 mod add_income;
 mod do_transfer;
 mod close_account;
+mod history;
+mod change_pin;
 
 pub use close::CloseCmd;
 pub use exit::ExitCmd;
-pub use create_account::CreateAccountCmd;
-pub use login::LoginCmd;
+pub use create_account::{CreateAccountCmd, create_account};
+pub use login::{LoginCmd, authenticate, login_error_kind};
 pub use balance::BalanceCmd;
 pub use add_income::AddIncomeCmd;
 pub use do_transfer::DoTransferCmd;
 pub use close_account::CloseAccountCmd;
+pub use history::HistoryCmd;
+pub use change_pin::ChangePinCmd;
+
+#[cfg(test)]
+pub use create_account::tests::get_mock_cmd as get_mock_create_account_cmd;
 
 use crate::Database;
 use crate::menu::MenuAction;
@@ -1,8 +1,12 @@
 use crate::menu::{MenuAction, Cmd};
 use crate::Database;
 use crate::command_line::read_with_prompt;
+use crate::money::{Money, MoneyError};
+use crate::database::ErrorKind;
+use crate::output::OutputFormat;
 
-use error_stack::{Context, IntoReport, Result, ResultExt};
+use error_stack::{Context, Report, Result, ResultExt};
+use serde_json::json;
 
 use std::fmt;
 
@@ -21,37 +25,44 @@ impl Context for AddIncomeError {}
 
 pub struct  AddIncomeCmd {
   card_number: String,
+  output: OutputFormat,
   read_from_cmd: Box<dyn Fn(&str) -> AddIncomeResult<String>>,
 }
 
 impl AddIncomeCmd {
-  pub fn new(card_number: &str) -> Self {
+  pub fn new(card_number: &str, output: OutputFormat) -> Self {
 
     AddIncomeCmd {
       card_number: card_number.to_owned(),
-      read_from_cmd: Box::new(|prompt: &str| {
-        read_with_prompt(prompt)
+      output,
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
           .change_context(AddIncomeError)
       }),
     }
   }
 
-  fn get_income(&self) -> AddIncomeResult<u32> {
+  fn get_income(&self) -> AddIncomeResult<Money> {
     let read_from_cmd = self.read_from_cmd.as_ref();
 
     let income_str = read_from_cmd(INCOME_AMOUNT_PROMPT)?;
 
-    let income = income_str.parse::<u32>()
-      .report()
+    let income = income_str.parse::<Money>()
       .attach_printable_lazy(|| {
         format!("invalid amount, parsed value: \"{}\"", income_str)
       })
       .change_context(AddIncomeError)?;
 
+    if !income.is_positive() {
+      return Err(Report::new(MoneyError::NotPositive))
+        .attach_printable(format!("amount must be positive, parsed value: \"{}\"", income_str))
+        .change_context(AddIncomeError);
+    }
+
     Ok(income)
   }
 
-  fn add_income_impl(&self, db: &mut dyn Database) -> AddIncomeResult<u32> {
+  fn add_income_impl(&self, db: &mut dyn Database) -> AddIncomeResult<Money> {
     let income = self.get_income()
       .attach_printable("failed to read from console")?;
 
@@ -75,10 +86,14 @@ impl Cmd for AddIncomeCmd {
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
     match self.add_income_impl(db) {
       Err(report) => {
-        println!("\n{report:?}");
+        self.output.print_error("deposit", ErrorKind::of(&report), &report, &format!("\n{report:?}"));
       }
       Ok(income) => {
-        println!("Added {} to your account", income);
+        self.output.print_ok(
+          "deposit",
+          json!({ "amount": income }),
+          &format!("Added {} to your account", income)
+        );
       },
     };
 
@@ -89,24 +104,17 @@ impl Cmd for AddIncomeCmd {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
 
-  #[test]
-  fn should_exec_add_income_cmd_json() {
-    exec_add_income(crate::database::json::tests::get_mock_db());
-  }
-
-  #[test]
-  fn should_exec_add_income_cmd_sqlite() {
-    exec_add_income(crate::database::sqlite::tests::get_mock_db());
-  }
+  backend_tests!(exec_add_income);
 
   fn exec_add_income(mut db: impl Database) {
     let mock_client = crate::database::tests::get_mock_client();
 
-    assert_eq!(mock_client.balance, 0);
+    assert_eq!(mock_client.balance, Money::ZERO);
 
     let card_number = mock_client.card_number.clone();
-    let one_thousand = String::from("1000");
+    let one_thousand = String::from("1000.50");
 
     let add_income_cmd = {
       use std::rc::Rc;
@@ -114,6 +122,7 @@ mod tests {
       let mock_income = Rc::new(one_thousand);
       AddIncomeCmd {
         card_number: card_number.clone(),
+        output: OutputFormat::Text,
         read_from_cmd: Box::new(move |prompt| {
           match prompt {
             INCOME_AMOUNT_PROMPT => Ok(mock_income.as_ref().clone()),
@@ -130,6 +139,21 @@ mod tests {
     assert_eq!(matches, true);
 
     let client = db.get_client(&card_number).expect("client with new balance");
-    assert_eq!(client.balance.to_string(), "1000")
+    assert_eq!(client.balance.to_string(), "1000.50")
+  }
+
+  #[test]
+  fn should_classify_invalid_income() {
+    for income in ["-1", "0", "abc"] {
+      let income = income.to_owned();
+      let add_income_cmd = AddIncomeCmd {
+        card_number: String::from("4000000000000000"),
+        output: OutputFormat::Json,
+        read_from_cmd: Box::new(move |_prompt| Ok(income.clone())),
+      };
+
+      let report = add_income_cmd.get_income().unwrap_err();
+      assert_eq!(ErrorKind::of(&report), ErrorKind::InvalidAmount);
+    }
   }
 }
@@ -1,14 +1,20 @@
 use crate::menu::{MenuAction, Cmd};
 use crate::Database;
+use crate::database::ErrorKind;
+use crate::output::OutputFormat;
+
+use serde_json::json;
 
 pub struct  BalanceCmd {
   card_number: String,
+  output: OutputFormat,
 }
 
 impl BalanceCmd {
-  pub fn new(card_number: &str) -> Self {
+  pub fn new(card_number: &str, output: OutputFormat) -> Self {
     BalanceCmd {
       card_number: card_number.to_owned(),
+      output,
     }
   }
 }
@@ -21,10 +27,19 @@ impl Cmd for BalanceCmd {
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
     match db.get_client(&self.card_number) {
       Err(error) => {
-        println!("\nfailed to get client data, error:{error:?}");
+        self.output.print_error(
+          "balance",
+          ErrorKind::of(&error),
+          &error,
+          &format!("\nfailed to get client data, error:{error:?}")
+        );
       },
       Ok(client) => {
-        println!("Your balance: {}", client.balance);
+        self.output.print_ok(
+          "balance",
+          json!({ "balance": client.balance, "currency": client.currency }),
+          &format!("Your balance: {} {}", client.balance, client.currency)
+        );
       }
     }
 
@@ -35,21 +50,14 @@ impl Cmd for BalanceCmd {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
 
-  #[test]
-  fn should_exec_balance_cmd_json() {
-    exec_balance_cmd(crate::database::json::tests::get_mock_db());
-  }
-
-  #[test]
-  fn should_exec_balance_cmd_sqlite() {
-    exec_balance_cmd(crate::database::sqlite::tests::get_mock_db());
-  }
+  backend_tests!(exec_balance_cmd);
 
 
   fn exec_balance_cmd(mut db: impl Database) {
     let mock_client = crate::database::tests::get_mock_client();
-    let balance_cmd = BalanceCmd::new(&mock_client.card_number);
+    let balance_cmd = BalanceCmd::new(&mock_client.card_number, OutputFormat::Json);
     db.save_new_client(mock_client).unwrap();
 
     let menu_action = balance_cmd.exec(&mut db);
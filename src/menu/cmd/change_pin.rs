@@ -0,0 +1,234 @@
+use crate::menu::{MenuAction, Cmd};
+use crate::Database;
+use crate::command_line::read_with_prompt;
+use crate::database::ErrorKind;
+use crate::output::OutputFormat;
+use crate::pin;
+use crate::menu::cmd::login::{authenticate, LoginError};
+
+use error_stack::{Context, Report, Result, ResultExt};
+use serde_json::json;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ChangePinError {
+  InvalidCurrentPin,
+  CardLocked,
+  PinsDoNotMatch,
+  InvalidNewPin,
+  HashingPinFailed,
+  ReadFromConsoleFailed,
+  DatabaseOperationFailed,
+}
+
+type ChangePinResult<T> = Result<T, ChangePinError>;
+type ReadFromCmd = Box<dyn Fn(&str) -> ChangePinResult<String>>;
+
+impl fmt::Display for ChangePinError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ChangePinError::InvalidCurrentPin => write!(f, "invalid current PIN"),
+      ChangePinError::CardLocked => write!(f, "card is locked after too many failed PIN attempts"),
+      ChangePinError::PinsDoNotMatch => write!(f, "new PINs do not match"),
+      ChangePinError::InvalidNewPin => write!(f, "new PIN rejected"),
+      ChangePinError::HashingPinFailed => write!(f, "failed to hash new PIN"),
+      ChangePinError::ReadFromConsoleFailed => write!(f, "failed to read from console"),
+      ChangePinError::DatabaseOperationFailed => write!(f, "failed to change PIN in database"),
+    }
+  }
+}
+
+impl Context for ChangePinError {}
+
+pub struct ChangePinCmd {
+  card_number: String,
+  max_pin_attempts: u32,
+  output: OutputFormat,
+  read_from_cmd: ReadFromCmd,
+}
+
+const CURRENT_PIN_PROMPT: &str = "Enter current PIN:";
+const NEW_PIN_PROMPT: &str = "Enter new PIN:";
+const REPEAT_PIN_PROMPT: &str = "Repeat new PIN:";
+
+impl ChangePinCmd {
+  pub fn new(card_number: &str, max_pin_attempts: u32, output: OutputFormat) -> Self {
+    ChangePinCmd {
+      card_number: card_number.to_owned(),
+      max_pin_attempts,
+      output,
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
+          .change_context(ChangePinError::ReadFromConsoleFailed)
+      }),
+    }
+  }
+
+  fn change_pin_impl(&self, db: &mut dyn Database) -> ChangePinResult<()> {
+    let read_from_cmd = self.read_from_cmd.as_ref();
+
+    let current_pin = read_from_cmd(CURRENT_PIN_PROMPT)?;
+
+    self.verify_current_pin(db, &current_pin)?;
+
+    let new_pin = read_from_cmd(NEW_PIN_PROMPT)?;
+    let repeated_pin = read_from_cmd(REPEAT_PIN_PROMPT)?;
+
+    if new_pin != repeated_pin {
+      return Err(Report::new(ChangePinError::PinsDoNotMatch));
+    }
+
+    pin::check_new_pin(&new_pin)
+      .change_context(ChangePinError::InvalidNewPin)?;
+
+    let pin_hash = pin::hash_pin(&new_pin)
+      .change_context(ChangePinError::HashingPinFailed)?;
+
+    db.update_pin(&self.card_number, &pin_hash)
+      .attach_printable_lazy(|| {
+        format!("failed to save new PIN, card_number: {}", self.card_number)
+      })
+      .change_context(ChangePinError::DatabaseOperationFailed)?;
+
+    Ok(())
+  }
+
+  /// Wrong current PIN counts as failed login, otherwise logged in session could guess it without limit
+  fn verify_current_pin(&self, db: &mut dyn Database, current_pin: &str) -> ChangePinResult<()> {
+    authenticate(db, &self.card_number, current_pin, self.max_pin_attempts)
+      .map(|_| ())
+      .map_err(|report| {
+        let context = match report.current_context() {
+          LoginError::InvalidLoginOrPin => ChangePinError::InvalidCurrentPin,
+          LoginError::CardLocked => ChangePinError::CardLocked,
+          _ => ChangePinError::DatabaseOperationFailed,
+        };
+
+        report.change_context(context)
+      })
+  }
+}
+
+impl Cmd for ChangePinCmd {
+  fn name(&self) -> &str {
+    "Change PIN"
+  }
+
+  fn exec(&self, db: &mut dyn Database) -> MenuAction {
+    match self.change_pin_impl(db) {
+      Err(report) => {
+        let kind = match report.current_context() {
+          ChangePinError::InvalidCurrentPin => ErrorKind::InvalidCredentials,
+          ChangePinError::CardLocked => ErrorKind::CardLocked,
+          ChangePinError::PinsDoNotMatch => ErrorKind::InvalidArgument,
+          _ => ErrorKind::of(&report),
+        };
+
+        self.output.print_error("change-pin", kind, &report, &format!("\n{report:?}"));
+
+        // locked card can't stay logged in
+        if let ChangePinError::CardLocked = report.current_context() {
+          return MenuAction::Close;
+        }
+      },
+      Ok(()) => {
+        self.output.print_ok("change-pin", json!({}), "PIN changed");
+      },
+    }
+
+    MenuAction::Render
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+
+  backend_tests!(
+    exec_change_pin_cmd,
+    reject_weak_pin,
+    lock_card_after_wrong_current_pins,
+  );
+
+  fn get_change_pin_cmd(card_number: &str, current_pin: &str, new_pin: &str, repeated_pin: &str) -> ChangePinCmd {
+    let current_pin = current_pin.to_owned();
+    let new_pin = new_pin.to_owned();
+    let repeated_pin = repeated_pin.to_owned();
+
+    ChangePinCmd {
+      card_number: card_number.to_owned(),
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          CURRENT_PIN_PROMPT => Ok(current_pin.clone()),
+          NEW_PIN_PROMPT => Ok(new_pin.clone()),
+          REPEAT_PIN_PROMPT => Ok(repeated_pin.clone()),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      }),
+    }
+  }
+
+  fn exec_change_pin_cmd(mut db: impl Database) {
+    let mock_client = crate::database::tests::get_mock_client();
+    let card_number = mock_client.card_number.clone();
+    db.save_new_client(mock_client).unwrap();
+
+    let change_pin_cmd = get_change_pin_cmd(&card_number, "1234", "7391", "7391");
+
+    let menu_action = change_pin_cmd.exec(&mut db);
+    assert!(matches!(menu_action, MenuAction::Render));
+
+    let stored_pin = db.get_client(&card_number).unwrap().pin;
+    assert!(pin::is_hashed(&stored_pin));
+    assert!(pin::verify_pin("7391", &stored_pin).unwrap());
+  }
+
+  fn reject_weak_pin(mut db: impl Database) {
+    let mock_client = crate::database::tests::get_mock_client();
+    let card_number = mock_client.card_number.clone();
+    db.save_new_client(mock_client).unwrap();
+
+    let cases = [
+      ("4321", "7391", "7391", "invalid current PIN"),
+      ("1234", "7391", "7392", "new PINs do not match"),
+      ("1234", "5555", "5555", "new PIN rejected"),
+    ];
+
+    for (current_pin, new_pin, repeated_pin, expected_error) in cases {
+      let change_pin_cmd = get_change_pin_cmd(&card_number, current_pin, new_pin, repeated_pin);
+
+      let report = change_pin_cmd.change_pin_impl(&mut db).unwrap_err();
+      assert_eq!(report.current_context().to_string(), expected_error);
+    }
+
+    // right current PIN upgrades plaintext PIN to hash as login does, but the PIN stays the same
+    assert!(pin::verify_pin("1234", &db.get_client(&card_number).unwrap().pin).unwrap());
+  }
+
+  fn lock_card_after_wrong_current_pins(mut db: impl Database) {
+    let mock_client = crate::database::tests::get_mock_client();
+    let card_number = mock_client.card_number.clone();
+    db.save_new_client(mock_client).unwrap();
+
+    let wrong_pin_cmd = get_change_pin_cmd(&card_number, "4321", "7391", "7391");
+
+    for _ in 0..2 {
+      let report = wrong_pin_cmd.change_pin_impl(&mut db).unwrap_err();
+      assert!(matches!(report.current_context(), ChangePinError::InvalidCurrentPin));
+    }
+
+    assert!(matches!(wrong_pin_cmd.exec(&mut db), MenuAction::Close));
+    assert!(db.get_login_attempts(&card_number).unwrap().locked);
+
+    // even the right PIN can't change locked card's PIN
+    let change_pin_cmd = get_change_pin_cmd(&card_number, "1234", "7391", "7391");
+    let report = change_pin_cmd.change_pin_impl(&mut db).unwrap_err();
+    assert!(matches!(report.current_context(), ChangePinError::CardLocked));
+
+    assert_eq!(db.get_client(&card_number).unwrap().pin, "1234");
+  }
+}
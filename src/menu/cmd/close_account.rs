@@ -1,14 +1,19 @@
 use crate::menu::{MenuAction, Cmd};
-use crate::database::Database;
+use crate::database::{Database, ErrorKind};
+use crate::output::OutputFormat;
+
+use serde_json::json;
 
 pub struct CloseAccountCmd {
   card_number: String,
+  output: OutputFormat,
 }
 
 impl CloseAccountCmd {
-  pub fn new(card_number: &str) -> Self {
+  pub fn new(card_number: &str, output: OutputFormat) -> Self {
     CloseAccountCmd {
       card_number: card_number.to_owned(),
+      output,
     }
   }
 }
@@ -21,13 +26,19 @@ impl Cmd for CloseAccountCmd {
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
     match db.remove_client(&self.card_number) {
       Err(error) => {
-        println!("\nclose account failed: {:?}", error);
+        self.output.print_error(
+          "close",
+          ErrorKind::of(&error),
+          &error,
+          &format!("\nclose account failed: {:?}", error)
+        );
         MenuAction::Render
       },
       Ok(client) => {
-        println!(
-          "Client with card_number: {} removed successfully",
-          client.card_number
+        self.output.print_ok(
+          "close",
+          json!({ "card_number": client.card_number, "balance": client.balance, "currency": client.currency }),
+          &format!("Client with card_number: {} removed successfully", client.card_number)
         );
         MenuAction::Close
       }
@@ -38,18 +49,11 @@ impl Cmd for CloseAccountCmd {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
 
-  #[test]
-  fn should_exec_create_account_cmd_json() {
-    exec_create_account_cmd(crate::database::json::tests::get_mock_db());
-  }
-
-  #[test]
-  fn should_exec_create_account_cmd_sqlite() {
-    exec_create_account_cmd(crate::database::sqlite::tests::get_mock_db());
-  }
+  backend_tests!(exec_close_account_cmd);
 
-  fn exec_create_account_cmd(mut db: impl Database) {
+  fn exec_close_account_cmd(mut db: impl Database) {
     assert_eq!(db.get_clients_count().unwrap(), 0);
 
     let mock_client = crate::database::tests::get_mock_client();
@@ -59,7 +63,7 @@ mod tests {
 
     assert_eq!(db.get_clients_count().unwrap(), 1);
 
-    let close_account_cmd = CloseAccountCmd::new(&card_number);
+    let close_account_cmd = CloseAccountCmd::new(&card_number, OutputFormat::Text);
 
     let menu_action = close_account_cmd.exec(&mut db);
 
@@ -1,16 +1,78 @@
 use crate::menu::{MenuAction, Cmd};
-use crate::database::{Database, Client};
-use crate::luhn::is_valid_card_number;
+use crate::database::{Database, Client, ErrorKind};
+use crate::card_number::{CardNetwork, CardNumberGenerator};
+use crate::pin::{check_new_pin, hash_pin, PIN_LENGTH};
+use crate::money::Money;
+use crate::currency::Currency;
+use crate::command_line::read_with_prompt;
+use crate::output::OutputFormat;
 
-use rand::prelude::{thread_rng, IteratorRandom};
+use error_stack::{Context, Result, ResultExt};
+use rand::{Rng, RngCore};
+use rand::rngs::OsRng;
+use serde_json::json;
 
-const DIGITS: &str = "0123456789";
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
 
-pub struct CreateAccountCmd;
+#[derive(Debug)]
+pub struct CreateAccountError;
+
+pub type CreateAccountResult<T> = Result<T, CreateAccountError>;
+type ReadFromCmd = Box<dyn Fn(&str) -> CreateAccountResult<String>>;
+
+impl fmt::Display for CreateAccountError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "creating client account failed")
+  }
+}
+
+impl Context for CreateAccountError {}
+
+const CURRENCY_PROMPT: &str = "Enter account currency (default PLN):";
+
+pub struct CreateAccountCmd {
+  card_numbers: Rc<CardNumberGenerator>,
+  output: OutputFormat,
+  /// Source of card numbers and PINs, OS generator outside of tests
+  rng: RefCell<Box<dyn RngCore>>,
+  read_from_cmd: ReadFromCmd,
+  print_to_cmd: Box<dyn Fn(&str)>,
+}
 
 impl CreateAccountCmd {
-  pub fn new() -> Self {
-    CreateAccountCmd
+  pub fn new(card_numbers: Rc<CardNumberGenerator>, output: OutputFormat) -> Self {
+    CreateAccountCmd {
+      card_numbers,
+      output,
+      rng: RefCell::new(Box::new(OsRng)),
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
+          .change_context(CreateAccountError)
+      }),
+      print_to_cmd: Box::new(|text: &str| println!("{}", text)),
+    }
+  }
+
+  fn create_account_impl(&self, db: &mut dyn Database) -> CreateAccountResult<NewAccount> {
+    let currency = self.read_currency()?;
+    let mut rng = self.rng.borrow_mut();
+
+    create_account(db, currency, &self.card_numbers, rng.as_mut())
+  }
+
+  fn read_currency(&self) -> CreateAccountResult<Currency> {
+    let read_from_cmd = self.read_from_cmd.as_ref();
+
+    let currency_str = read_from_cmd(CURRENCY_PROMPT)?;
+
+    if currency_str.is_empty() {
+      return Ok(Currency::default());
+    }
+
+    currency_str.to_uppercase().parse::<Currency>()
+      .change_context(CreateAccountError)
   }
 }
 
@@ -20,90 +82,150 @@ impl Cmd for CreateAccountCmd {
   }
 
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
-    let mut card_number;
-    loop {
-      card_number = generate_card_number();
-
-      let has_client = match db.has_client(&card_number) {
-        Err(error) => {
-          println!("\ncreating client account failed: {:?}", error);
-          return MenuAction::Render
-        },
-        Ok(has_client) => has_client,
-      };
-
-      if !has_client {
-        break;
-      }
-    }
-
-    let pin = generate_pin();
-    let new_client = Client {
-      card_number: card_number.clone(),
-      pin: pin.clone(),
-      balance: 0,
-    };
+    let print_to_cmd = self.print_to_cmd.as_ref();
 
-    match db.save_new_client(new_client) {
+    let text = match self.create_account_impl(db) {
       Err(error) => {
-        println!("\ncreating client account failed: {:?}", error);
+        self.output.format_error("create-account", ErrorKind::of(&error), &error, &format!("\n{:?}", error))
       }
-      Ok(_) => {
-        println!("New client created");
-        println!("card_number: {}", card_number);
-        println!("pin: {}", pin);
+      Ok(new_account) => {
+        self.output.format_ok(
+          "create-account",
+          json!({
+            "card_number": new_account.card_number,
+            "pin": new_account.pin,
+            "currency": new_account.currency,
+            "network": new_account.network,
+          }),
+          &format!(
+            "New client created\ncard_number: {}\npin: {}\ncurrency: {}",
+            new_account.card_number,
+            new_account.pin,
+            new_account.currency
+          )
+        )
       },
-    }
+    };
+
+    print_to_cmd(&text);
 
     MenuAction::Render
   }
 }
 
-fn generate_card_number() -> String {
+/// Credentials of created account, `pin` is plaintext and shown to the client only once
+#[derive(Debug)]
+pub struct NewAccount {
+  pub card_number: String,
+  pub pin: String,
+  pub currency: Currency,
+  /// None for issuer ranges outside of the known networks
+  pub network: Option<CardNetwork>,
+}
+
+pub fn create_account(
+  db: &mut dyn Database,
+  currency: Currency,
+  card_numbers: &CardNumberGenerator,
+  rng: &mut dyn RngCore
+) -> CreateAccountResult<NewAccount> {
+  let card_number = card_numbers.generate(db, rng)
+    .change_context(CreateAccountError)?;
+  let network = CardNumberGenerator::network(&card_number);
+
+  let pin = generate_pin(rng);
+  let pin_hash = hash_pin(&pin)
+    .change_context(CreateAccountError)?;
+
+  let new_client = Client {
+    card_number: card_number.clone(),
+    pin: pin_hash,
+    balance: Money::ZERO,
+    currency,
+  };
+
+  db.save_new_client(new_client)
+    .attach_printable_lazy(|| {
+      format!("failed to save new client, card_number: {}", card_number)
+    })
+    .change_context(CreateAccountError)?;
+
+  Ok(NewAccount { card_number, pin, currency, network })
+}
+
+/// Random PIN which passes the same checks as PIN chosen by the client
+fn generate_pin(rng: &mut dyn RngCore) -> String {
   loop {
-    let mut card_number = String::from("400000");
-    let digits =  DIGITS;
-    let mut rng = thread_rng();
-    for _ in 0..10 {
-      let num = digits.chars().choose(&mut rng).unwrap(); // FIXME
-      card_number.push(num);
-    }
-    if is_valid_card_number(&card_number) {
-      return card_number
+    let pin: String = (0..PIN_LENGTH)
+      .map(|_| char::from_digit(rng.gen_range(0..10), 10).expect("digit is below 10"))
+      .collect();
+
+    if check_new_pin(&pin).is_ok() {
+      return pin;
     }
   }
 }
 
-fn generate_pin() -> String {
-  let mut pin = String::new();
-  let digits =  DIGITS;
-  let mut rng = thread_rng();
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+  use rand::SeedableRng;
+  use rand::rngs::SmallRng;
+
+  pub fn get_mock_cmd(currency: &str) -> CreateAccountCmd {
+    let currency = currency.to_owned();
 
-  for _ in 0..4 {
-    let num = digits.chars().choose(&mut rng).unwrap(); // FIXME
-    pin.push(num);
+    CreateAccountCmd {
+      card_numbers: Rc::new(CardNumberGenerator::default()),
+      output: OutputFormat::Text,
+      rng: RefCell::new(Box::new(SmallRng::seed_from_u64(0))),
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          CURRENCY_PROMPT => Ok(currency.clone()),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      }),
+      print_to_cmd: Box::new(|text| println!("{}", text)),
+    }
   }
 
-  pin
-}
+  /// Replaces printing with collecting printed texts
+  fn capture_output(cmd: &mut CreateAccountCmd) -> Rc<RefCell<Vec<String>>> {
+    let printed = Rc::new(RefCell::new(Vec::new()));
+    let printed_copy = printed.clone();
 
+    cmd.print_to_cmd = Box::new(move |text| printed_copy.borrow_mut().push(text.to_owned()));
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+    printed
+  }
+
+  backend_tests!(
+    exec_create_account_cmd,
+    print_new_account_as_json,
+    create_predictable_accounts,
+  );
 
   #[test]
-  fn should_exec_create_account_cmd_json() {
-    exec_create_account_cmd(crate::database::json::tests::get_mock_db());
+  fn should_read_account_currency() {
+    assert_eq!(get_mock_cmd("").read_currency().unwrap(), Currency::PLN);
+    assert_eq!(get_mock_cmd("eur").read_currency().unwrap().to_string(), "EUR");
+    assert!(get_mock_cmd("EURO").read_currency().is_err());
   }
 
   #[test]
-  fn should_exec_create_account_cmd_sqlite() {
-    exec_create_account_cmd(crate::database::sqlite::tests::get_mock_db());
+  fn should_generate_only_strong_pins() {
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    for _ in 0..1000 {
+      let pin = generate_pin(&mut rng);
+      assert!(check_new_pin(&pin).is_ok(), "{pin}");
+    }
   }
 
   fn exec_create_account_cmd(mut db: impl Database) {
-    let create_account_cmd = CreateAccountCmd::new();
+    let mut create_account_cmd = get_mock_cmd("USD");
+    let printed = capture_output(&mut create_account_cmd);
 
     assert_eq!(db.get_clients_count().unwrap(), 0);
 
@@ -112,5 +234,49 @@ mod tests {
     let matches = matches!(menu_action, MenuAction::Render);
     assert_eq!(matches, true);
     assert_eq!(db.get_clients_count().unwrap(), 1);
+
+    let client = db.get_client("4000004948260926").unwrap();
+    assert_eq!(client.currency.to_string(), "USD");
+    assert!(crate::pin::verify_pin("2729", &client.pin).unwrap());
+
+    assert_eq!(
+      *printed.borrow(),
+      ["New client created\ncard_number: 4000004948260926\npin: 2729\ncurrency: USD"]
+    );
+  }
+
+  fn print_new_account_as_json(mut db: impl Database) {
+    let mut create_account_cmd = get_mock_cmd("EUR");
+    create_account_cmd.output = OutputFormat::Json;
+    let printed = capture_output(&mut create_account_cmd);
+
+    create_account_cmd.exec(&mut db);
+
+    let printed = printed.borrow();
+    assert_eq!(printed.len(), 1);
+
+    let json: serde_json::Value = serde_json::from_str(&printed[0]).unwrap();
+    assert_eq!(json, json!({
+      "command": "create-account",
+      "status": "ok",
+      "result": {
+        "card_number": "4000004948260926",
+        "pin": "2729",
+        "currency": "EUR",
+        "network": "visa",
+      },
+    }));
+  }
+
+  fn create_predictable_accounts(mut db: impl Database) {
+    let new_account = get_mock_cmd("").create_account_impl(&mut db).unwrap();
+    assert_eq!(new_account.card_number, "4000004948260926");
+    assert_eq!(new_account.pin, "2729");
+    assert_eq!(new_account.network, Some(CardNetwork::Visa));
+
+    // same seed draws the taken card number first, so the next one is used
+    let new_account = get_mock_cmd("").create_account_impl(&mut db).unwrap();
+    assert_eq!(new_account.card_number, "4000007290848032");
+    assert_eq!(new_account.pin, "9340");
   }
 }
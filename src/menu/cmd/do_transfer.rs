@@ -1,17 +1,36 @@
 use crate::menu::{MenuAction, Cmd};
 use crate::Database;
 use crate::command_line::read_with_prompt;
+use crate::money::{Money, MoneyError};
+use crate::currency::ExchangeRates;
+use crate::database::ErrorKind;
+use crate::luhn::validate_card_number;
+use crate::output::OutputFormat;
 
-use error_stack::{Context, IntoReport, Result, ResultExt};
+use error_stack::{Context, Report, Result, ResultExt};
+use serde_json::json;
 
 use std::fmt;
+use std::rc::Rc;
 
-#[derive(Debug)]
-pub struct DoTransferError;
+#[derive(Debug, PartialEq, Eq)]
+pub enum DoTransferError {
+  InvalidReceiverCardNumber,
+  TransferToOwnCard,
+  InvalidAmount,
+  ReadFromConsoleFailed,
+  DatabaseOperationFailed,
+}
 
 impl fmt::Display for DoTransferError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "transfer failed")
+    match self {
+      DoTransferError::InvalidReceiverCardNumber => write!(f, "invalid receiver card number, probably mistyped"),
+      DoTransferError::TransferToOwnCard => write!(f, "cannot transfer to your own card"),
+      DoTransferError::InvalidAmount => write!(f, "invalid transfer amount"),
+      DoTransferError::ReadFromConsoleFailed => write!(f, "failed to read from console"),
+      DoTransferError::DatabaseOperationFailed => write!(f, "transfer failed"),
+    }
   }
 }
 
@@ -21,6 +40,8 @@ type DoTransferResult<T> = Result<T, DoTransferError>;
 
 pub struct  DoTransferCmd {
   card_number: String,
+  rates: Rc<ExchangeRates>,
+  output: OutputFormat,
   read_from_cmd: Box<dyn Fn(&str) -> DoTransferResult<String>>,
 }
 
@@ -28,30 +49,49 @@ const RECEIVER_CARD_PROMPT: &str = "Enter receiver card number:";
 const AMOUNT_PROMPT: &str = "Enter amount:";
 
 impl DoTransferCmd {
-  pub fn new(card_number: &str) -> Self {
+  pub fn new(card_number: &str, rates: Rc<ExchangeRates>, output: OutputFormat) -> Self {
     DoTransferCmd {
       card_number: card_number.to_owned(),
-      read_from_cmd: Box::new(|prompt: &str| {
-        read_with_prompt(prompt)
-          .change_context(DoTransferError)
+      rates,
+      output,
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
+          .change_context(DoTransferError::ReadFromConsoleFailed)
       }),
     }
   }
 
-  fn do_transfer_impl(&self, db: &mut dyn Database) -> DoTransferResult<u32> {
+  fn do_transfer_impl(&self, db: &mut dyn Database) -> DoTransferResult<Money> {
     let read_from_cmd = &self.read_from_cmd;
 
-    let receiver_card_number = read_from_cmd(RECEIVER_CARD_PROMPT)?;
+    let receiver_card_number_str = read_from_cmd(RECEIVER_CARD_PROMPT)?;
+
+    // checked before asking for the amount, so a typo does not reach the database
+    let receiver_card_number = validate_card_number(&receiver_card_number_str)
+      .attach_printable_lazy(|| {
+        format!("receiver_card_number: \"{}\"", receiver_card_number_str)
+      })
+      .change_context(DoTransferError::InvalidReceiverCardNumber)?;
+
+    if receiver_card_number == self.card_number {
+      return Err(Report::new(DoTransferError::TransferToOwnCard));
+    }
+
     let amount_str = read_from_cmd(AMOUNT_PROMPT)?;
 
-    let amount = amount_str.parse::<u32>()
-      .report()
+    let amount = amount_str.parse::<Money>()
       .attach_printable_lazy(|| {
         format!("failed to parse transfer amount, amount_str: \"{}\"", amount_str)
       })
-      .change_context(DoTransferError)?;
+      .change_context(DoTransferError::InvalidAmount)?;
+
+    if !amount.is_positive() {
+      return Err(Report::new(MoneyError::NotPositive))
+        .attach_printable(format!("amount must be positive, amount_str: \"{}\"", amount_str))
+        .change_context(DoTransferError::InvalidAmount);
+    }
 
-    db.transfer_funds(amount, &self.card_number, &receiver_card_number)
+    db.transfer_funds(amount, &self.card_number, &receiver_card_number, &self.rates)
       .attach_printable_lazy(|| {
         format!(
           "transfer funds failed, amount: {} sender_card_number: {} receiver_card_number: {}",
@@ -60,7 +100,7 @@ impl DoTransferCmd {
           receiver_card_number
         )
       })
-      .change_context(DoTransferError)?;
+      .change_context(DoTransferError::DatabaseOperationFailed)?;
 
     Ok(amount)
   }
@@ -74,10 +114,19 @@ impl Cmd for DoTransferCmd {
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
     match self.do_transfer_impl(db) {
       Err(error) => {
-        println!("\nerror: {error:?}");
+        let kind = match error.current_context() {
+          DoTransferError::InvalidReceiverCardNumber | DoTransferError::TransferToOwnCard => ErrorKind::InvalidArgument,
+          _ => ErrorKind::of(&error),
+        };
+
+        self.output.print_error("transfer", kind, &error, &format!("\nerror: {error:?}"));
       },
       Ok(amount) => {
-        println!("transferred: {}", amount);
+        self.output.print_ok(
+          "transfer",
+          json!({ "amount": amount }),
+          &format!("transferred: {}", amount)
+        );
       }
     }
 
@@ -88,44 +137,97 @@ impl Cmd for DoTransferCmd {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
+
+  backend_tests!(
+    exec_do_transfer_cmd,
+    convert_currency,
+    reject_invalid_receiver,
+  );
+
+  fn get_do_transfer_cmd(
+    card_number: &str,
+    receiver_card_number: &str,
+    amount: &str,
+    rates: ExchangeRates
+  ) -> DoTransferCmd {
+    let receiver_card_number = receiver_card_number.to_owned();
+    let amount = amount.to_owned();
 
-  #[test]
-  fn should_exec_do_transfer_cmd_json() {
-    exec_do_transfer_cmd(crate::database::json::tests::get_mock_db());
+    DoTransferCmd {
+      card_number: card_number.to_owned(),
+      rates: Rc::new(rates),
+      output: OutputFormat::Text,
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          RECEIVER_CARD_PROMPT => Ok(receiver_card_number.clone()),
+          AMOUNT_PROMPT => Ok(amount.clone()),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      }),
+    }
   }
 
-  #[test]
-  fn should_exec_do_transfer_cmd_sqlite() {
-    exec_do_transfer_cmd(crate::database::sqlite::tests::get_mock_db());
+  fn convert_currency(mut db: impl Database) {
+    use crate::currency::{Currency, Rate};
+
+    let mut sender = crate::database::tests::get_mock_client();
+    sender.balance = Money::from_minor_units(10_000);
+    sender.currency = "EUR".parse::<Currency>().unwrap();
+
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000002");
+
+    let sender_card_number = sender.card_number.clone();
+    let receiver_card_number = receiver.card_number.clone();
+
+    db.save_new_client(sender).unwrap();
+    db.save_new_client(receiver).unwrap();
+
+    let do_transfer_cmd = get_do_transfer_cmd(
+      &sender_card_number,
+      &receiver_card_number,
+      "10",
+      crate::currency::tests::get_mock_rates()
+    );
+    let amount = do_transfer_cmd.do_transfer_impl(&mut db).unwrap();
+    assert_eq!(amount.to_string(), "10.00");
+
+    assert_eq!(db.get_client(&sender_card_number).unwrap().balance.to_string(), "90.00");
+    assert_eq!(db.get_client(&receiver_card_number).unwrap().balance.to_string(), "43.20");
+
+    let history = db.get_history(&receiver_card_number).unwrap();
+    assert_eq!(history[0].amount.to_string(), "43.20");
+    assert_eq!(history[0].exchange_rate, Some(Rate::from_micro_units(4_320_000)));
+
+    let do_transfer_cmd = get_do_transfer_cmd(
+      &sender_card_number,
+      &receiver_card_number,
+      "10",
+      ExchangeRates::default()
+    );
+    assert!(do_transfer_cmd.do_transfer_impl(&mut db).is_err());
+    assert_eq!(db.get_client(&sender_card_number).unwrap().balance.to_string(), "90.00");
   }
 
   fn exec_do_transfer_cmd(mut db: impl Database) {
-    let mock_client1 = crate::database::tests::get_mock_client();
+    let mut mock_client1 = crate::database::tests::get_mock_client();
+    mock_client1.card_number = String::from("4000000000000002");
     let mut mock_client2 = crate::database::tests::get_mock_client();
     mock_client2.card_number = String::from("4000000000000001");
-    mock_client2.balance = 5000;
+    mock_client2.balance = Money::from_minor_units(500_000);
 
 
     let sender_card_number = mock_client2.card_number.clone();
     let receiver_card_number = mock_client1.card_number.clone();
-    let one_thousand = String::from("1000");
-
-    let do_transfer_cmd = {
-      use std::rc::Rc;
-
-      let receiver_mock = Rc::new(receiver_card_number.clone());
-      let amount_mock = Rc::new(one_thousand);
-      DoTransferCmd {
-        card_number: sender_card_number.clone(),
-        read_from_cmd: Box::new(move |prompt| {
-          match prompt {
-            RECEIVER_CARD_PROMPT => Ok(receiver_mock.as_ref().clone()),
-            AMOUNT_PROMPT => Ok(amount_mock.as_ref().clone()),
-            _prompt => panic!("unknown prompt: {_prompt}"),
-          }
-        }),
-      }
-    };
+    let one_thousand = String::from("1000.25");
+
+    let do_transfer_cmd = get_do_transfer_cmd(
+      &sender_card_number,
+      &receiver_card_number,
+      &one_thousand,
+      ExchangeRates::default()
+    );
     db.save_new_client(mock_client1).unwrap();
     db.save_new_client(mock_client2).unwrap();
 
@@ -137,7 +239,43 @@ mod tests {
     let sender_client = db.get_client(&sender_card_number).unwrap();
     let receiver_client = db.get_client(&receiver_card_number).unwrap();
 
-    assert_eq!(sender_client.balance.to_string(), "4000");
-    assert_eq!(receiver_client.balance.to_string(), "1000");
+    assert_eq!(sender_client.balance.to_string(), "3999.75");
+    assert_eq!(receiver_client.balance.to_string(), "1000.25");
+  }
+
+  fn reject_invalid_receiver(mut db: impl Database) {
+    let mut sender = crate::database::tests::get_mock_client();
+    sender.card_number = String::from("4000000000000002");
+    sender.balance = Money::from_minor_units(10_000);
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000010");
+
+    let sender_card_number = sender.card_number.clone();
+    let receiver_card_number = receiver.card_number.clone();
+
+    db.save_new_client(sender).unwrap();
+    db.save_new_client(receiver).unwrap();
+
+    let cases = [
+      ("4000000000000011", DoTransferError::InvalidReceiverCardNumber),
+      ("4000 0000 0000 001", DoTransferError::InvalidReceiverCardNumber),
+      ("4000o00000000010", DoTransferError::InvalidReceiverCardNumber),
+      ("", DoTransferError::InvalidReceiverCardNumber),
+      ("4000000000000002", DoTransferError::TransferToOwnCard),
+      ("4000 0000 0000 0002", DoTransferError::TransferToOwnCard),
+    ];
+
+    for (receiver_card_number_str, error) in cases {
+      let do_transfer_cmd = get_do_transfer_cmd(&sender_card_number, receiver_card_number_str, "10", ExchangeRates::default());
+      let report = do_transfer_cmd.do_transfer_impl(&mut db).unwrap_err();
+
+      assert_eq!(report.current_context(), &error, "{receiver_card_number_str:?}");
+    }
+
+    assert_eq!(db.get_client(&sender_card_number).unwrap().balance.to_string(), "100.00");
+
+    let do_transfer_cmd = get_do_transfer_cmd(&sender_card_number, "4000-0000-0000-0010", "10", ExchangeRates::default());
+    do_transfer_cmd.do_transfer_impl(&mut db).unwrap();
+    assert_eq!(db.get_client(&receiver_card_number).unwrap().balance.to_string(), "10.00");
   }
 }
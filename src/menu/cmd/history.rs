@@ -0,0 +1,214 @@
+use crate::menu::{MenuAction, Cmd};
+use crate::Database;
+use crate::database::{ErrorKind, Transaction};
+use crate::command_line::read_with_prompt;
+use crate::output::OutputFormat;
+
+use error_stack::{Context, Result, ResultExt};
+use serde_json::json;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub struct HistoryError;
+
+type HistoryResult<T> = Result<T, HistoryError>;
+type ReadFromCmd = Box<dyn Fn(&str) -> HistoryResult<String>>;
+
+impl fmt::Display for HistoryError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "failed to show transaction history")
+  }
+}
+
+impl Context for HistoryError {}
+
+pub struct HistoryCmd {
+  card_number: String,
+  page_size: usize,
+  output: OutputFormat,
+  read_from_cmd: ReadFromCmd,
+}
+
+const PAGE_SIZE: usize = 10;
+const PAGE_PROMPT: &str = "n - next page, p - previous page, q - back to menu:";
+
+impl HistoryCmd {
+  pub fn new(card_number: &str, output: OutputFormat) -> Self {
+    HistoryCmd {
+      card_number: card_number.to_owned(),
+      page_size: PAGE_SIZE,
+      output,
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
+          .change_context(HistoryError)
+      }),
+    }
+  }
+
+  /// Transactions of the logged in card, newest first
+  fn get_history(&self, db: &dyn Database) -> HistoryResult<Vec<Transaction>> {
+    let mut history = db.get_history(&self.card_number)
+      .attach_printable_lazy(|| {
+        format!("failed to get history of card_number: {}", self.card_number)
+      })
+      .change_context(HistoryError)?;
+
+    history.reverse();
+
+    Ok(history)
+  }
+
+  fn pages_count(&self, history: &[Transaction]) -> usize {
+    history.len().div_ceil(self.page_size)
+  }
+
+  fn get_page<'a>(&self, history: &'a [Transaction], page: usize) -> &'a [Transaction] {
+    let start = page * self.page_size;
+    let end = usize::min(start + self.page_size, history.len());
+
+    &history[start..end]
+  }
+
+  fn print_page(&self, history: &[Transaction], page: usize) {
+    println!("Transactions, page {} of {}:", page + 1, self.pages_count(history));
+
+    for transaction in self.get_page(history, page) {
+      print_transaction(transaction);
+    }
+  }
+
+  fn history_impl(&self, db: &dyn Database) -> HistoryResult<()> {
+    let read_from_cmd = self.read_from_cmd.as_ref();
+
+    let history = self.get_history(db)?;
+
+    if self.output == OutputFormat::Json {
+      self.output.print_ok("history", json!({ "transactions": history }), "");
+      return Ok(());
+    }
+
+    if history.is_empty() {
+      println!("No transactions");
+      return Ok(());
+    }
+
+    let pages_count = self.pages_count(&history);
+    let mut page = 0;
+
+    loop {
+      self.print_page(&history, page);
+
+      if pages_count == 1 {
+        return Ok(());
+      }
+
+      loop {
+        match read_from_cmd(PAGE_PROMPT)?.as_str() {
+          "n" if page + 1 < pages_count => page += 1,
+          "n" => println!("This is the last page"),
+          "p" if page > 0 => page -= 1,
+          "p" => println!("This is the first page"),
+          "q" => return Ok(()),
+          _ => {
+            println!("|Unknown command|");
+            continue;
+          },
+        }
+
+        break;
+      }
+    }
+  }
+}
+
+fn print_transaction(transaction: &Transaction) {
+  let date = chrono::DateTime::from_timestamp(transaction.timestamp as i64, 0)
+    .map(|date| date.to_string())
+    .unwrap_or_else(|| transaction.timestamp.to_string());
+
+  let counterparty = match &transaction.counterparty {
+    None => String::new(),
+    Some(card_number) => format!(" ({})", card_number),
+  };
+
+  let exchange_rate = match transaction.exchange_rate {
+    None => String::new(),
+    Some(rate) => format!(", rate: {}", rate),
+  };
+
+  println!(
+    "#{} {} {}{}: {}, balance: {}{}",
+    transaction.id,
+    date,
+    transaction.kind,
+    counterparty,
+    transaction.amount,
+    transaction.balance,
+    exchange_rate
+  );
+}
+
+impl Cmd for HistoryCmd {
+  fn name(&self) -> &str {
+    "History"
+  }
+
+  fn exec(&self, db: &mut dyn Database) -> MenuAction {
+    if let Err(report) = self.history_impl(db) {
+      self.output.print_error("history", ErrorKind::of(&report), &report, &format!("\n{report:?}"));
+    }
+
+    MenuAction::Render
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+
+  backend_tests!(exec_history_cmd);
+
+  fn exec_history_cmd(mut db: impl Database) {
+    use crate::money::Money;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mock_client = crate::database::tests::get_mock_client();
+    let card_number = mock_client.card_number.clone();
+
+    db.save_new_client(mock_client).unwrap();
+    for funds in 1..=5 {
+      db.add_funds(Money::from_minor_units(funds), &card_number).unwrap();
+    }
+
+    let answers = Rc::new(RefCell::new(vec!["q", "p", "x", "n", "n", "n"]));
+    let answers_copy = answers.clone();
+
+    let history_cmd = HistoryCmd {
+      card_number: card_number.clone(),
+      page_size: 2,
+      output: OutputFormat::Text,
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          PAGE_PROMPT => Ok(
+            answers_copy.borrow_mut().pop().expect("read_from_cmd called to many times").to_owned()
+          ),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      }),
+    };
+
+    let history = history_cmd.get_history(&db).unwrap();
+    let amounts: Vec<i64> = history.iter().map(|t| t.amount.minor_units()).collect();
+    assert_eq!(amounts, [5, 4, 3, 2, 1]);
+    assert_eq!(history_cmd.pages_count(&history), 3);
+    assert_eq!(history_cmd.get_page(&history, 2).len(), 1);
+
+    let menu_action = history_cmd.exec(&mut db);
+
+    assert!(matches!(menu_action, MenuAction::Render));
+    assert!(answers.borrow().is_empty());
+  }
+}
@@ -2,25 +2,37 @@ use crate::menu::{MenuAction, Cmd};
 use crate::Database;
 use crate::command_line::read_with_prompt;
 use crate::Client;
+use crate::database::ErrorKind;
+use crate::output::OutputFormat;
+use crate::pin;
 
 use error_stack::{Context, Report, Result, ResultExt};
+use serde_json::json;
 
 use std::fmt;
 
 #[derive(Debug)]
 pub enum LoginError {
   InvalidLoginOrPin,
+  CardLocked,
   GettingClientFailed,
+  SavingLoginAttemptsFailed,
+  VerifyingPinFailed,
+  UpgradingPinFailed,
   ReadFromConsoleFailed,
 }
 
-type LoginResult<T> = Result<T, LoginError>;
+pub type LoginResult<T> = Result<T, LoginError>;
 
 impl fmt::Display for LoginError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match &self {
       LoginError::InvalidLoginOrPin => write!(f, "invalid login or PIN"),
+      LoginError::CardLocked => write!(f, "card is locked after too many failed PIN attempts"),
       LoginError::GettingClientFailed => write!(f, "failed to get client from database"),
+      LoginError::SavingLoginAttemptsFailed => write!(f, "failed to save login attempts"),
+      LoginError::VerifyingPinFailed => write!(f, "failed to verify PIN"),
+      LoginError::UpgradingPinFailed => write!(f, "failed to replace plaintext PIN with hash"),
       LoginError::ReadFromConsoleFailed => write!(f, "failed to read from console"),
     }
   }
@@ -29,14 +41,18 @@ impl fmt::Display for LoginError {
 impl Context for LoginError {}
 
 pub struct  LoginCmd {
+  max_pin_attempts: u32,
+  output: OutputFormat,
   read_from_cmd: Box<dyn Fn(&str) -> LoginResult<String>>,
 }
 
 impl LoginCmd {
-  pub fn new() -> Self {
+  pub fn new(max_pin_attempts: u32, output: OutputFormat) -> Self {
     LoginCmd {
-      read_from_cmd: Box::new(|prompt: &str| {
-        read_with_prompt(prompt)
+      max_pin_attempts,
+      output,
+      read_from_cmd: Box::new(move |prompt: &str| {
+        read_with_prompt(prompt, output)
           .change_context(LoginError::ReadFromConsoleFailed)
       }),
     }
@@ -48,30 +64,92 @@ impl LoginCmd {
     let login = read_from_cmd(LOGIN_PROMPT)?;
     let pin = read_from_cmd(PIN_PROMPT)?;
 
-    match db.has_client(&login) {
-      Err(error) => {
-        return Err(error)
-          .change_context(LoginError::GettingClientFailed)
-      },
-      Ok(has_client) => {
-        if !has_client {
-          return Err(Report::new(LoginError::InvalidLoginOrPin));
-        }
-      },
-    }
+    authenticate(db, &login, &pin, self.max_pin_attempts)
+  }
+}
+
+/// Checks the PIN, counts failed attempts and upgrades legacy plaintext PIN on success
+pub fn authenticate(db: &mut dyn Database, login: &str, pin: &str, max_pin_attempts: u32) -> LoginResult<Client> {
+  match db.has_client(login) {
+    Err(error) => {
+      return Err(error)
+        .change_context(LoginError::GettingClientFailed)
+    },
+    Ok(has_client) => {
+      if !has_client {
+        return Err(Report::new(LoginError::InvalidLoginOrPin));
+      }
+    },
+  }
+
+  let client = db.get_client(login)
+    .attach_printable(format!("login: {login}"))
+    .change_context(LoginError::GettingClientFailed)?;
 
-    let client = db.get_client(&login)
+  let attempts = db.get_login_attempts(login)
+    .attach_printable(format!("login: {login}"))
+    .change_context(LoginError::GettingClientFailed)?;
+
+  if attempts.locked {
+    return Err(Report::new(LoginError::CardLocked));
+  }
+
+  let pin_matches = pin::verify_pin(pin, &client.pin)
+    .attach_printable(format!("login: {login}"))
+    .change_context(LoginError::VerifyingPinFailed)?;
+
+  if !pin_matches {
+    return Err(register_failed_login(db, login, max_pin_attempts));
+  }
+
+  if attempts.failed > 0 {
+    db.reset_login_attempts(login)
       .attach_printable(format!("login: {login}"))
-      .change_context(LoginError::GettingClientFailed)?;
+      .change_context(LoginError::SavingLoginAttemptsFailed)?;
+  }
 
-    if client.pin != pin {
-      return Err(Report::new(LoginError::InvalidLoginOrPin));
-    }
+  if !pin::is_hashed(&client.pin) {
+    upgrade_pin(db, &client, pin)?;
+  }
+
+  Ok(client)
+}
 
-    Ok(client)
+pub fn login_error_kind(report: &Report<LoginError>) -> ErrorKind {
+  match report.current_context() {
+    LoginError::InvalidLoginOrPin => ErrorKind::InvalidCredentials,
+    LoginError::CardLocked => ErrorKind::CardLocked,
+    _ => ErrorKind::of(report),
   }
 }
 
+fn register_failed_login(db: &mut dyn Database, login: &str, max_pin_attempts: u32) -> Report<LoginError> {
+  match db.register_failed_login(login, max_pin_attempts) {
+    Err(error) => error
+      .attach_printable(format!("login: {login}"))
+      .change_context(LoginError::SavingLoginAttemptsFailed),
+    Ok(attempts) if attempts.locked => Report::new(LoginError::CardLocked),
+    Ok(attempts) => Report::new(LoginError::InvalidLoginOrPin)
+      .attach_printable(format!(
+        "{} attempts left",
+        max_pin_attempts.saturating_sub(attempts.failed)
+      )),
+  }
+}
+
+fn upgrade_pin(db: &mut dyn Database, client: &Client, pin: &str) -> LoginResult<()> {
+  let hash = pin::hash_pin(pin)
+    .change_context(LoginError::UpgradingPinFailed)?;
+
+  db.update_pin(&client.card_number, &hash)
+    .attach_printable_lazy(|| {
+      format!("failed to save hashed PIN, card_number: {}", client.card_number)
+    })
+    .change_context(LoginError::UpgradingPinFailed)?;
+
+  Ok(())
+}
+
 const LOGIN_PROMPT: &str = "Enter login:";
 const PIN_PROMPT: &str = "Enter PIN:";
 
@@ -83,13 +161,21 @@ impl Cmd for LoginCmd {
   fn exec(&self, db: &mut dyn Database) -> MenuAction {
     match self.login_impl(db) {
       Err(report) => {
-        print_report(report);
+        self.output.print_error(
+          "login",
+          login_error_kind(&report),
+          &report,
+          &format!("\nlogin failed: {report:?}")
+        );
 
         MenuAction::Render
       },
       Ok(client) => {
-        println!("Login successful");
-        println!("logged in on client: {:?}", client);
+        self.output.print_ok(
+          "login",
+          json!({ "card_number": client.card_number }),
+          &format!("Login successful\nlogged in on client: {:?}", client)
+        );
 
         MenuAction::RenderLoginMenu(client.card_number)
       },
@@ -97,32 +183,26 @@ impl Cmd for LoginCmd {
   }
 }
 
-fn print_report(error: Report<LoginError>) {
-  println!("\nlogin failed: {error:?}");
-}
-
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::database::tests::backend_tests;
+  use crate::database::LoginAttempts;
 
-  #[test]
-  fn should_exec_login_json() {
-    let mut db = crate::database::json::tests::get_mock_db();
-    exec_login(&mut db);
-  }
-
-  #[test]
-  fn should_exec_login_sqlite() {
-    let mut db = crate::database::sqlite::tests::get_mock_db();
-    exec_login(&mut db);
-  }
+  backend_tests!(
+    exec_login,
+    reject_wrong_pin,
+    lock_card,
+  );
 
-  fn exec_login(db: &mut dyn Database) {
+  fn exec_login(mut db: impl Database) {
     let mock_client = crate::database::tests::get_mock_client();
     db.save_new_client(mock_client.clone()).unwrap();
 
     let client = mock_client.clone();
     let login_cmd = LoginCmd {
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
       read_from_cmd: Box::new(move |prompt| {
         match prompt {
           LOGIN_PROMPT => Ok(client.card_number.clone()),
@@ -132,7 +212,7 @@ mod tests {
       })
     };
 
-    let menu_action = login_cmd.exec(db);
+    let menu_action = login_cmd.exec(&mut db);
 
     let success = match menu_action {
       MenuAction::RenderLoginMenu(card_number) => {
@@ -141,5 +221,74 @@ mod tests {
       _ => false
     };
     assert_eq!(success, true);
+
+    let stored_pin = db.get_client(&mock_client.card_number).unwrap().pin;
+    assert!(pin::is_hashed(&stored_pin));
+    assert!(pin::verify_pin(&mock_client.pin, &stored_pin).unwrap());
+
+    let menu_action = login_cmd.exec(&mut db);
+    assert!(matches!(menu_action, MenuAction::RenderLoginMenu(_)));
+  }
+
+  fn reject_wrong_pin(mut db: impl Database) {
+    let mut mock_client = crate::database::tests::get_mock_client();
+    mock_client.pin = pin::hash_pin("1234").unwrap();
+    db.save_new_client(mock_client.clone()).unwrap();
+
+    let login_cmd = LoginCmd {
+      max_pin_attempts: 3,
+      output: OutputFormat::Text,
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          LOGIN_PROMPT => Ok(mock_client.card_number.clone()),
+          PIN_PROMPT => Ok(String::from("4321")),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      })
+    };
+
+    let report = login_cmd.login_impl(&mut db).unwrap_err();
+
+    assert!(matches!(report.current_context(), LoginError::InvalidLoginOrPin));
+  }
+
+  fn lock_card(mut db: impl Database) {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mock_client = crate::database::tests::get_mock_client();
+    let card_number = mock_client.card_number.clone();
+    db.save_new_client(mock_client.clone()).unwrap();
+
+    let pin_mock = Rc::new(RefCell::new(String::from("4321")));
+    let pin_mock_copy = pin_mock.clone();
+
+    let login_cmd = LoginCmd {
+      max_pin_attempts: 2,
+      output: OutputFormat::Text,
+      read_from_cmd: Box::new(move |prompt| {
+        match prompt {
+          LOGIN_PROMPT => Ok(mock_client.card_number.clone()),
+          PIN_PROMPT => Ok(pin_mock_copy.borrow().clone()),
+          _prompt => panic!("unknown prompt: {_prompt}"),
+        }
+      })
+    };
+
+    let report = login_cmd.login_impl(&mut db).unwrap_err();
+    assert!(matches!(report.current_context(), LoginError::InvalidLoginOrPin));
+
+    let report = login_cmd.login_impl(&mut db).unwrap_err();
+    assert!(matches!(report.current_context(), LoginError::CardLocked));
+
+    pin_mock.replace(String::from("1234"));
+
+    let report = login_cmd.login_impl(&mut db).unwrap_err();
+    assert!(matches!(report.current_context(), LoginError::CardLocked));
+
+    db.reset_login_attempts(&card_number).unwrap();
+
+    login_cmd.login_impl(&mut db).unwrap();
+    assert_eq!(db.get_login_attempts(&card_number).unwrap(), LoginAttempts::default());
   }
 }
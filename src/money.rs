@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use error_stack::{Context, Report};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum MoneyError {
+  InvalidFormat(String),
+  NotPositive,
+  Overflow,
+}
+
+impl fmt::Display for MoneyError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      MoneyError::InvalidFormat(str) => write!(f, "invalid amount \"{str}\", expected format like 12.34"),
+      MoneyError::NotPositive => write!(f, "amount must be positive"),
+      MoneyError::Overflow => write!(f, "amount is too large"),
+    }
+  }
+}
+
+impl Context for MoneyError {}
+
+const MINOR_UNITS_IN_UNIT: i64 = 100;
+const DECIMAL_PLACES: usize = 2;
+
+/// Amount of money as integer number of minor units (cents)
+///
+/// Serialized as decimal string, plain JSON integers are read as whole units,
+/// because that is how balances were stored before minor units were introduced.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "MoneyRepr", into = "String")]
+pub struct Money(i64);
+
+impl Money {
+  pub const ZERO: Money = Money(0);
+
+  pub const fn from_minor_units(minor_units: i64) -> Self {
+    Money(minor_units)
+  }
+
+  pub fn from_units(units: i64) -> Option<Self> {
+    units.checked_mul(MINOR_UNITS_IN_UNIT).map(Money)
+  }
+
+  pub const fn minor_units(self) -> i64 {
+    self.0
+  }
+
+  pub fn checked_add(self, other: Money) -> Option<Money> {
+    self.0.checked_add(other.0).map(Money)
+  }
+
+  pub fn checked_sub(self, other: Money) -> Option<Money> {
+    self.0.checked_sub(other.0).map(Money)
+  }
+
+  pub const fn is_positive(self) -> bool {
+    self.0 > 0
+  }
+
+  pub const fn is_negative(self) -> bool {
+    self.0 < 0
+  }
+}
+
+impl fmt::Display for Money {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let sign = if self.is_negative() { "-" } else { "" };
+    let abs = self.0.unsigned_abs();
+    let minor_units_in_unit = MINOR_UNITS_IN_UNIT as u64;
+
+    write!(
+      f,
+      "{}{}.{:0width$}",
+      sign,
+      abs / minor_units_in_unit,
+      abs % minor_units_in_unit,
+      width = DECIMAL_PLACES
+    )
+  }
+}
+
+impl FromStr for Money {
+  type Err = Report<MoneyError>;
+
+  fn from_str(str: &str) -> Result<Self, Self::Err> {
+    let invalid_format = || Report::new(MoneyError::InvalidFormat(str.to_owned()));
+
+    let (negative, unsigned) = match str.strip_prefix('-') {
+      None => (false, str),
+      Some(unsigned) => (true, unsigned),
+    };
+
+    let (units_str, fraction_str) = match unsigned.split_once('.') {
+      None => (unsigned, ""),
+      Some((units_str, fraction_str)) => (units_str, fraction_str),
+    };
+
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+
+    if units_str.is_empty()
+      || fraction_str.len() > DECIMAL_PLACES
+      || (unsigned.contains('.') && fraction_str.is_empty())
+      || !is_digits(units_str)
+      || !is_digits(fraction_str)
+    {
+      return Err(invalid_format());
+    }
+
+    let units = units_str
+      .parse::<i64>()
+      .map_err(|_| Report::new(MoneyError::Overflow))?;
+
+    let fraction = format!("{:0<width$}", fraction_str, width = DECIMAL_PLACES)
+      .parse::<i64>()
+      .map_err(|_| invalid_format())?;
+
+    let minor_units = units
+      .checked_mul(MINOR_UNITS_IN_UNIT)
+      .and_then(|minor_units| minor_units.checked_add(fraction))
+      .ok_or_else(|| Report::new(MoneyError::Overflow))?;
+
+    Ok(Money(if negative { -minor_units } else { minor_units }))
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoneyRepr {
+  Units(i64),
+  Decimal(String),
+}
+
+impl TryFrom<MoneyRepr> for Money {
+  type Error = String;
+
+  fn try_from(repr: MoneyRepr) -> Result<Self, Self::Error> {
+    match repr {
+      MoneyRepr::Units(units) => Money::from_units(units)
+        .ok_or_else(|| MoneyError::Overflow.to_string()),
+      MoneyRepr::Decimal(str) => str.parse()
+        .map_err(|report: Report<MoneyError>| report.current_context().to_string()),
+    }
+  }
+}
+
+impl From<Money> for String {
+  fn from(money: Money) -> Self {
+    money.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_parse_amounts() {
+    let cases = [
+      ("12.34", 1234),
+      ("12.3", 1230),
+      ("12", 1200),
+      ("0.05", 5),
+      ("-0.5", -50),
+      ("007.00", 700),
+    ];
+
+    for (str, minor_units) in cases {
+      assert_eq!(str.parse::<Money>().unwrap(), Money::from_minor_units(minor_units), "{str}");
+    }
+  }
+
+  #[test]
+  fn should_reject_invalid_amounts() {
+    for str in ["", "-", ".5", "12.", "12.345", "1,5", "12a", "+1", " 1", "1e3"] {
+      let report = str.parse::<Money>().unwrap_err();
+      assert!(matches!(report.current_context(), MoneyError::InvalidFormat(_)), "{str}");
+    }
+
+    let report = "92233720368547758.08".parse::<Money>().unwrap_err();
+    assert!(matches!(report.current_context(), MoneyError::Overflow));
+  }
+
+  #[test]
+  fn should_format_amounts() {
+    assert_eq!(Money::from_minor_units(1234).to_string(), "12.34");
+    assert_eq!(Money::from_minor_units(5).to_string(), "0.05");
+    assert_eq!(Money::from_minor_units(-50).to_string(), "-0.50");
+    assert_eq!(Money::ZERO.to_string(), "0.00");
+    assert_eq!(Money::from_minor_units(i64::MIN).to_string(), "-92233720368547758.08");
+  }
+
+  #[test]
+  fn should_use_checked_arithmetic() {
+    let max = Money::from_minor_units(i64::MAX);
+    let one = Money::from_minor_units(1);
+
+    assert_eq!(max.checked_add(one), None);
+    assert_eq!(Money::from_minor_units(i64::MIN).checked_sub(one), None);
+    assert_eq!(one.checked_sub(one), Some(Money::ZERO));
+    assert_eq!(Money::from_units(i64::MAX), None);
+  }
+
+  #[test]
+  fn should_serialize_as_decimal_string() {
+    let json = serde_json::to_string(&Money::from_minor_units(1234)).unwrap();
+    assert_eq!(json, "\"12.34\"");
+
+    let money: Money = serde_json::from_str("\"12.34\"").unwrap();
+    assert_eq!(money, Money::from_minor_units(1234));
+
+    let legacy: Money = serde_json::from_str("12").unwrap();
+    assert_eq!(legacy, Money::from_minor_units(1200));
+  }
+}
@@ -0,0 +1,83 @@
+use crate::database::ErrorKind;
+
+use clap::ValueEnum;
+use error_stack::{Context, Report};
+use serde_json::{json, Value};
+
+/// How results of commands are printed, `Json` prints one JSON object per command
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+  Text,
+  Json,
+}
+
+impl OutputFormat {
+  pub fn print_ok(self, command: &str, result: Value, text: &str) {
+    println!("{}", self.format_ok(command, result, text));
+  }
+
+  /// What `print_ok` prints, for commands which print somewhere else in tests
+  pub fn format_ok(self, command: &str, result: Value, text: &str) -> String {
+    match self {
+      OutputFormat::Text => text.to_owned(),
+      OutputFormat::Json => ok_json(command, result).to_string(),
+    }
+  }
+
+  /// Menus, prompts and hints for the person at the terminal, in JSON mode they go to stderr,
+  /// so stdout carries only the JSON objects
+  pub fn print_text(self, text: &str) {
+    match self {
+      OutputFormat::Text => println!("{}", text),
+      OutputFormat::Json => eprintln!("{}", text),
+    }
+  }
+
+  pub fn print_error<C: Context>(self, command: &str, kind: ErrorKind, report: &Report<C>, text: &str) {
+    println!("{}", self.format_error(command, kind, report, text));
+  }
+
+  pub fn format_error<C: Context>(self, command: &str, kind: ErrorKind, report: &Report<C>, text: &str) -> String {
+    match self {
+      OutputFormat::Text => text.to_owned(),
+      OutputFormat::Json => error_json(command, kind, report).to_string(),
+    }
+  }
+}
+
+pub fn ok_json(command: &str, result: Value) -> Value {
+  json!({
+    "command": command,
+    "status": "ok",
+    "result": result,
+  })
+}
+
+/// Only the top level error message is included, the full report is for humans
+pub fn error_json<C: Context>(command: &str, kind: ErrorKind, report: &Report<C>) -> Value {
+  json!({
+    "command": command,
+    "status": "error",
+    "error": kind,
+    "message": report.current_context().to_string(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::DatabaseError;
+
+  #[test]
+  fn should_build_json_objects() {
+    let json = ok_json("balance", json!({ "balance": "12.34" }));
+    assert_eq!(json.to_string(), r#"{"command":"balance","result":{"balance":"12.34"},"status":"ok"}"#);
+
+    let report = Report::new(DatabaseError::JSON);
+    let json = error_json("balance", ErrorKind::ClientNotFound, &report);
+    assert_eq!(
+      json.to_string(),
+      r#"{"command":"balance","error":"client_not_found","message":"database operation failed","status":"error"}"#
+    );
+  }
+}
@@ -0,0 +1,149 @@
+use argon2::Argon2;
+use argon2::password_hash::{
+  rand_core::OsRng,
+  PasswordHash,
+  PasswordHasher,
+  PasswordVerifier,
+  SaltString,
+};
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use subtle::ConstantTimeEq;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PinError {
+  HashingFailed,
+  InvalidHash,
+  InvalidFormat,
+  TooWeak,
+}
+
+pub type PinResult<T> = Result<T, PinError>;
+
+impl fmt::Display for PinError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PinError::HashingFailed => write!(f, "PIN hashing failed"),
+      PinError::InvalidHash => write!(f, "stored PIN hash is malformed"),
+      PinError::InvalidFormat => write!(f, "PIN must consist of {PIN_LENGTH} digits"),
+      PinError::TooWeak => write!(f, "PIN is too easy to guess"),
+    }
+  }
+}
+
+impl Context for PinError {}
+
+const HASH_PREFIX: &str = "$argon2";
+pub const PIN_LENGTH: usize = 4;
+
+/// Salted Argon2 hash of the PIN in PHC string format
+pub fn hash_pin(pin: &str) -> PinResult<String> {
+  let salt = SaltString::generate(&mut OsRng);
+
+  let hash = Argon2::default().hash_password(pin.as_bytes(), &salt)
+    .report()
+    .change_context(PinError::HashingFailed)?;
+
+  Ok(hash.to_string())
+}
+
+/// Legacy records keep the PIN in plaintext, those have to be upgraded with `hash_pin`
+pub fn is_hashed(stored_pin: &str) -> bool {
+  stored_pin.starts_with(HASH_PREFIX)
+}
+
+/// Constant time comparison of the PIN against stored hash or legacy plaintext PIN
+pub fn verify_pin(pin: &str, stored_pin: &str) -> PinResult<bool> {
+  if !is_hashed(stored_pin) {
+    return Ok(pin.as_bytes().ct_eq(stored_pin.as_bytes()).into());
+  }
+
+  let hash = PasswordHash::new(stored_pin)
+    .report()
+    .change_context(PinError::InvalidHash)?;
+
+  Ok(Argon2::default().verify_password(pin.as_bytes(), &hash).is_ok())
+}
+
+/// Rejects PINs of wrong format, repeated digits (eg. 1111) and sequences (eg. 1234, 9876)
+pub fn check_new_pin(pin: &str) -> PinResult<()> {
+  let digits: Vec<i32> = pin
+    .chars()
+    .filter_map(|character| character.to_digit(10))
+    .map(|digit| digit as i32)
+    .collect();
+
+  if pin.len() != PIN_LENGTH || digits.len() != PIN_LENGTH {
+    return Err(Report::new(PinError::InvalidFormat));
+  }
+
+  let steps: Vec<i32> = digits
+    .windows(2)
+    .map(|pair| pair[1] - pair[0])
+    .collect();
+
+  let is_pattern = [0, 1, -1]
+    .iter()
+    .any(|step| steps.iter().all(|s| s == step));
+
+  if is_pattern {
+    return Err(Report::new(PinError::TooWeak))
+      .attach_printable("repeated digits or sequence");
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn should_verify_hashed_pin() {
+    let hash = hash_pin("1234").unwrap();
+
+    assert!(is_hashed(&hash));
+    assert!(verify_pin("1234", &hash).unwrap());
+    assert!(!verify_pin("4321", &hash).unwrap());
+  }
+
+  #[test]
+  fn should_salt_hashes() {
+    assert_ne!(hash_pin("1234").unwrap(), hash_pin("1234").unwrap());
+  }
+
+  #[test]
+  fn should_verify_legacy_plaintext_pin() {
+    assert!(!is_hashed("1234"));
+    assert!(verify_pin("1234", "1234").unwrap());
+    assert!(!verify_pin("123", "1234").unwrap());
+  }
+
+  #[test]
+  fn should_reject_malformed_hash() {
+    assert!(verify_pin("1234", "$argon2id$v=19$m=19456,t=2,p=1$!!!$!!!").is_err());
+  }
+
+  #[test]
+  fn should_accept_strong_pin() {
+    assert!(check_new_pin("7391").is_ok());
+    assert!(check_new_pin("1243").is_ok());
+  }
+
+  #[test]
+  fn should_reject_weak_pins() {
+    for pin in ["0000", "7777", "1234", "0123", "6789", "4321", "9876"] {
+      let report = check_new_pin(pin).unwrap_err();
+      assert!(matches!(report.current_context(), PinError::TooWeak), "pin: {pin}");
+    }
+  }
+
+  #[test]
+  fn should_reject_invalid_pins() {
+    for pin in ["", "123", "12345", "12a4", "１２３４"] {
+      let report = check_new_pin(pin).unwrap_err();
+      assert!(matches!(report.current_context(), PinError::InvalidFormat), "pin: {pin}");
+    }
+  }
+}
@@ -0,0 +1,470 @@
+use crate::database::{Database, ErrorKind};
+use crate::currency::{Currency, ExchangeRates};
+use crate::card_number::CardNumberGenerator;
+use crate::money::{Money, MoneyError};
+use crate::menu::{authenticate, create_account, login_error_kind};
+use crate::output::{error_json, ok_json};
+use crate::luhn::validate_card_number;
+
+use error_stack::{Context, IntoReport, Report, Result, ResultExt};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ServerError {
+  StartingFailed,
+  InvalidRequest,
+  Unauthorized,
+  RouteNotFound,
+  MethodNotAllowed,
+  OperationFailed(ErrorKind),
+}
+
+pub type ServerResult<T> = Result<T, ServerError>;
+
+impl fmt::Display for ServerError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ServerError::StartingFailed => write!(f, "starting HTTP server failed"),
+      ServerError::InvalidRequest => write!(f, "invalid request body"),
+      ServerError::Unauthorized => write!(f, "missing or invalid session token"),
+      ServerError::RouteNotFound => write!(f, "route not found"),
+      ServerError::MethodNotAllowed => write!(f, "method not allowed"),
+      ServerError::OperationFailed(kind) => write!(f, "{}", kind),
+    }
+  }
+}
+
+impl Context for ServerError {}
+
+impl ServerError {
+  fn kind(&self) -> ErrorKind {
+    match self {
+      ServerError::InvalidRequest => ErrorKind::InvalidArgument,
+      ServerError::Unauthorized => ErrorKind::InvalidCredentials,
+      ServerError::OperationFailed(kind) => *kind,
+      _ => ErrorKind::Other,
+    }
+  }
+
+  fn status_code(&self) -> u16 {
+    match self {
+      ServerError::InvalidRequest => 400,
+      ServerError::Unauthorized => 401,
+      ServerError::RouteNotFound => 404,
+      ServerError::MethodNotAllowed => 405,
+      ServerError::StartingFailed => 500,
+      ServerError::OperationFailed(kind) => match kind {
+        ErrorKind::InvalidAmount | ErrorKind::InvalidArgument => 400,
+        ErrorKind::InvalidCredentials => 401,
+        ErrorKind::ClientNotFound => 404,
+        ErrorKind::ClientAlreadyExists => 409,
+        ErrorKind::InsufficientFunds | ErrorKind::CurrencyConversionFailed => 422,
+        ErrorKind::CardLocked => 423,
+        ErrorKind::Other => 500,
+      },
+    }
+  }
+
+  fn from_report<C>(report: Report<C>) -> Report<ServerError> {
+    let kind = ErrorKind::of(&report);
+
+    report.change_context(ServerError::OperationFailed(kind))
+  }
+}
+
+pub struct Request<'a> {
+  pub method: &'a str,
+  pub path: &'a str,
+  pub token: Option<&'a str>,
+  pub body: &'a str,
+}
+
+#[derive(Debug)]
+pub struct Response {
+  pub status_code: u16,
+  pub body: Value,
+}
+
+#[derive(Deserialize)]
+struct CreateAccountBody {
+  currency: Option<Currency>,
+}
+
+#[derive(Deserialize)]
+struct LoginBody {
+  card_number: String,
+  pin: String,
+}
+
+#[derive(Deserialize)]
+struct DepositBody {
+  amount: Money,
+}
+
+#[derive(Deserialize)]
+struct TransferBody {
+  to: String,
+  amount: Money,
+}
+
+/// Routes requests to database operations, logged in clients are identified by session tokens
+pub struct Server<'a> {
+  rates: &'a ExchangeRates,
+  card_numbers: &'a CardNumberGenerator,
+  max_pin_attempts: u32,
+  sessions: HashMap<String, String>,
+}
+
+impl<'a> Server<'a> {
+  pub fn new(rates: &'a ExchangeRates, card_numbers: &'a CardNumberGenerator, max_pin_attempts: u32) -> Self {
+    Server {
+      rates,
+      card_numbers,
+      max_pin_attempts,
+      sessions: HashMap::new(),
+    }
+  }
+
+  pub fn handle(&mut self, db: &mut dyn Database, request: &Request) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+
+    let (command, result) = match (request.method, path) {
+      ("POST", "/accounts") => ("create-account", self.create_account(db, request)),
+      ("POST", "/sessions") => ("login", self.login(db, request)),
+      ("DELETE", "/sessions") => ("logout", self.logout(request)),
+      ("GET", "/balance") => ("balance", self.balance(db, request)),
+      ("POST", "/deposit") => ("deposit", self.deposit(db, request)),
+      ("POST", "/transfer") => ("transfer", self.transfer(db, request)),
+      ("DELETE", "/account") => ("close", self.close(db, request)),
+      (_, "/accounts" | "/sessions" | "/balance" | "/deposit" | "/transfer" | "/account") => {
+        ("", Err(Report::new(ServerError::MethodNotAllowed)))
+      },
+      _ => ("", Err(Report::new(ServerError::RouteNotFound))),
+    };
+
+    match result {
+      Ok((status_code, result)) => Response {
+        status_code,
+        body: ok_json(command, result),
+      },
+      Err(report) => Response {
+        status_code: report.current_context().status_code(),
+        body: error_json(command, report.current_context().kind(), &report),
+      },
+    }
+  }
+
+  fn create_account(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let body: CreateAccountBody = parse_body(request)?;
+
+    let new_account = create_account(db, body.currency.unwrap_or_default(), self.card_numbers, &mut OsRng)
+      .map_err(ServerError::from_report)?;
+
+    Ok((201, json!({
+      "card_number": new_account.card_number,
+      "pin": new_account.pin,
+      "currency": new_account.currency,
+      "network": new_account.network,
+    })))
+  }
+
+  fn login(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let body: LoginBody = parse_body(request)?;
+
+    let client = authenticate(db, &body.card_number, &body.pin, self.max_pin_attempts)
+      .map_err(|report| {
+        let kind = login_error_kind(&report);
+        report.change_context(ServerError::OperationFailed(kind))
+      })?;
+
+    let token = generate_token();
+    self.sessions.insert(token.clone(), client.card_number);
+
+    Ok((201, json!({ "token": token })))
+  }
+
+  fn logout(&mut self, request: &Request) -> ServerResult<(u16, Value)> {
+    self.get_session(request)?;
+
+    if let Some(token) = request.token {
+      self.sessions.remove(token);
+    }
+
+    Ok((200, json!({})))
+  }
+
+  fn balance(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let card_number = self.get_session(request)?;
+
+    get_balance(db, &card_number)
+  }
+
+  fn deposit(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let card_number = self.get_session(request)?;
+    let body: DepositBody = parse_body(request)?;
+    check_amount(body.amount)?;
+
+    db.add_funds(body.amount, &card_number)
+      .map_err(ServerError::from_report)?;
+
+    get_balance(db, &card_number)
+  }
+
+  fn transfer(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let card_number = self.get_session(request)?;
+    let body: TransferBody = parse_body(request)?;
+    check_amount(body.amount)?;
+
+    let receiver_card_number = validate_card_number(&body.to)
+      .attach_printable_lazy(|| format!("receiver_card_number: \"{}\"", body.to))
+      .map_err(ServerError::from_report)?;
+
+    db.transfer_funds(body.amount, &card_number, &receiver_card_number, self.rates)
+      .attach_printable_lazy(|| {
+        format!(
+          "amount: {} sender_card_number: {} receiver_card_number: {}",
+          body.amount,
+          card_number,
+          receiver_card_number
+        )
+      })
+      .map_err(ServerError::from_report)?;
+
+    get_balance(db, &card_number)
+  }
+
+  fn close(&mut self, db: &mut dyn Database, request: &Request) -> ServerResult<(u16, Value)> {
+    let card_number = self.get_session(request)?;
+
+    let client = db.remove_client(&card_number)
+      .map_err(ServerError::from_report)?;
+
+    self.sessions.retain(|_, session_card_number| *session_card_number != card_number);
+
+    Ok((200, json!({
+      "card_number": client.card_number,
+      "balance": client.balance,
+      "currency": client.currency,
+    })))
+  }
+
+  fn get_session(&self, request: &Request) -> ServerResult<String> {
+    request.token
+      .and_then(|token| self.sessions.get(token))
+      .cloned()
+      .ok_or_else(|| Report::new(ServerError::Unauthorized))
+  }
+}
+
+/// Serves requests one by one until the process is killed
+pub fn serve(
+  db: &mut dyn Database,
+  rates: &ExchangeRates,
+  card_numbers: &CardNumberGenerator,
+  max_pin_attempts: u32,
+  address: &str
+) -> ServerResult<()> {
+  let http_server = tiny_http::Server::http(address)
+    .map_err(|error| {
+      Report::new(ServerError::StartingFailed)
+        .attach_printable(format!("address: {}, error: {}", address, error))
+    })?;
+
+  eprintln!("Listening on http://{}", address);
+
+  let mut server = Server::new(rates, card_numbers, max_pin_attempts);
+  let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json")
+    .expect("valid header");
+
+  for mut request in http_server.incoming_requests() {
+    let mut body = String::new();
+    let token = request.headers()
+      .iter()
+      .find(|header| header.field.equiv("Authorization"))
+      .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+      .map(str::to_owned);
+
+    let response = match request.as_reader().read_to_string(&mut body) {
+      Err(error) => {
+        let report = Report::new(ServerError::InvalidRequest)
+          .attach_printable(error.to_string());
+
+        Response {
+          status_code: 400,
+          body: error_json("", report.current_context().kind(), &report),
+        }
+      },
+      Ok(_) => server.handle(db, &Request {
+        method: request.method().as_str(),
+        path: request.url(),
+        token: token.as_deref(),
+        body: &body,
+      }),
+    };
+
+    let http_response = tiny_http::Response::from_string(response.body.to_string())
+      .with_status_code(response.status_code)
+      .with_header(content_type.clone());
+
+    if let Err(error) = request.respond(http_response) {
+      eprintln!("responding failed: {}", error);
+    }
+  }
+
+  Ok(())
+}
+
+fn parse_body<T: DeserializeOwned>(request: &Request) -> ServerResult<T> {
+  let body = if request.body.trim().is_empty() { "{}" } else { request.body };
+
+  serde_json::from_str(body)
+    .report()
+    .change_context(ServerError::InvalidRequest)
+}
+
+fn check_amount(amount: Money) -> ServerResult<()> {
+  if !amount.is_positive() {
+    return Err(Report::new(MoneyError::NotPositive))
+      .attach_printable(format!("amount: {}", amount))
+      .map_err(ServerError::from_report);
+  }
+
+  Ok(())
+}
+
+fn get_balance(db: &dyn Database, card_number: &str) -> ServerResult<(u16, Value)> {
+  let client = db.get_client(card_number)
+    .map_err(ServerError::from_report)?;
+
+  Ok((200, json!({ "balance": client.balance, "currency": client.currency })))
+}
+
+fn generate_token() -> String {
+  let mut bytes = [0u8; 32];
+  OsRng.fill_bytes(&mut bytes);
+
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::tests::backend_tests;
+
+  backend_tests!(
+    handle_requests,
+    map_errors_to_status_codes,
+  );
+
+  fn send(
+    server: &mut Server,
+    db: &mut dyn Database,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &str
+  ) -> Response {
+    server.handle(db, &Request { method, path, token, body })
+  }
+
+  fn login(server: &mut Server, db: &mut dyn Database, card_number: &str, pin: &str) -> Response {
+    let body = json!({ "card_number": card_number, "pin": pin }).to_string();
+
+    send(server, db, "POST", "/sessions", None, &body)
+  }
+
+  fn handle_requests(mut db: impl Database) {
+    let rates = ExchangeRates::default();
+    let card_numbers = CardNumberGenerator::default();
+    let mut server = Server::new(&rates, &card_numbers, 3);
+
+    let mut receiver = crate::database::tests::get_mock_client();
+    receiver.card_number = String::from("4000000000000002");
+    let receiver_card_number = receiver.card_number.clone();
+    db.save_new_client(receiver).unwrap();
+
+    let response = send(&mut server, &mut db, "POST", "/accounts", None, "");
+    assert_eq!(response.status_code, 201, "{:?}", response);
+    let card_number = response.body["result"]["card_number"].as_str().unwrap().to_owned();
+    let pin = response.body["result"]["pin"].as_str().unwrap().to_owned();
+
+    let response = login(&mut server, &mut db, &card_number, &pin);
+    assert_eq!(response.status_code, 201, "{:?}", response);
+    let token = response.body["result"]["token"].as_str().unwrap().to_owned();
+    let token = Some(token.as_str());
+
+    let response = send(&mut server, &mut db, "POST", "/deposit", token, r#"{"amount": "10.50"}"#);
+    assert_eq!(response.status_code, 200, "{:?}", response);
+    assert_eq!(response.body["result"]["balance"], "10.50");
+
+    let body = json!({ "to": receiver_card_number, "amount": "0.50" }).to_string();
+    let response = send(&mut server, &mut db, "POST", "/transfer", token, &body);
+    assert_eq!(response.status_code, 200, "{:?}", response);
+
+    let response = send(&mut server, &mut db, "GET", "/balance", token, "");
+    assert_eq!(response.body["result"], json!({ "balance": "10.00", "currency": "PLN" }));
+    assert_eq!(db.get_client(&receiver_card_number).unwrap().balance.to_string(), "0.50");
+
+    let response = send(&mut server, &mut db, "DELETE", "/account", token, "");
+    assert_eq!(response.status_code, 200, "{:?}", response);
+    assert!(!db.has_client(&card_number).unwrap());
+
+    let response = send(&mut server, &mut db, "GET", "/balance", token, "");
+    assert_eq!(response.status_code, 401);
+  }
+
+  fn map_errors_to_status_codes(mut db: impl Database) {
+    let rates = ExchangeRates::default();
+    let card_numbers = CardNumberGenerator::default();
+    let mut server = Server::new(&rates, &card_numbers, 2);
+
+    let mut client = crate::database::tests::get_mock_client();
+    client.card_number = String::from("4000000000000002");
+    let card_number = client.card_number.clone();
+    db.save_new_client(client).unwrap();
+
+    let response = send(&mut server, &mut db, "GET", "/balance", Some("invalid"), "");
+    assert_eq!(response.status_code, 401);
+
+    let response = send(&mut server, &mut db, "GET", "/missing", None, "");
+    assert_eq!(response.status_code, 404);
+
+    let response = send(&mut server, &mut db, "PUT", "/balance", None, "");
+    assert_eq!(response.status_code, 405);
+
+    let response = send(&mut server, &mut db, "POST", "/sessions", None, "{");
+    assert_eq!(response.status_code, 400);
+
+    let response = login(&mut server, &mut db, &card_number, "1234");
+    let token = response.body["result"]["token"].as_str().unwrap().to_owned();
+    let token = Some(token.as_str());
+
+    let cases = [
+      ("POST", "/deposit", r#"{"amount": "-1"}"#, 400, "invalid_amount"),
+      ("POST", "/transfer", r#"{"to": "4000000000000010", "amount": "1"}"#, 404, "client_not_found"),
+      ("POST", "/transfer", r#"{"to": "4000000000000001", "amount": "1"}"#, 400, "invalid_argument"),
+      ("POST", "/transfer", r#"{"to": "4000000000000002", "amount": "1"}"#, 400, "invalid_argument"),
+    ];
+
+    for (method, path, body, status_code, error) in cases {
+      let response = send(&mut server, &mut db, method, path, token, body);
+      assert_eq!(response.status_code, status_code, "{:?}", response);
+      assert_eq!(response.body["error"], error);
+    }
+
+    let response = login(&mut server, &mut db, &card_number, "0000");
+    assert_eq!(response.status_code, 401);
+    assert_eq!(response.body["error"], "invalid_credentials");
+
+    let response = login(&mut server, &mut db, &card_number, "0000");
+    assert_eq!(response.status_code, 423);
+    assert_eq!(response.body["error"], "card_locked");
+  }
+}
@@ -0,0 +1,89 @@
+use serde_json::Value;
+
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Runs the program in JSON mode with `input` typed in, returns exit code and JSON objects printed to stdout
+fn run(args: &[&OsStr], input: &str) -> (Option<i32>, Vec<Value>) {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_rust-bank"))
+    .args(["--output", "json"])
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+
+  child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+  let output = child.wait_with_output().unwrap();
+
+  let objects = String::from_utf8(output.stdout)
+    .unwrap()
+    .lines()
+    .map(|line| serde_json::from_str(line).unwrap_or_else(|error| panic!("{error}: {line:?}")))
+    .collect();
+
+  (output.status.code(), objects)
+}
+
+/// Runs the interactive menu on JSON database in `data_dir`
+fn run_menu(data_dir: &Path, input: &str) -> Vec<Value> {
+  let (exit_code, objects) = run(&["json".as_ref(), "--data-dir".as_ref(), data_dir.as_os_str()], input);
+  assert_eq!(exit_code, Some(0));
+
+  objects
+}
+
+#[test]
+fn should_print_only_json_from_menu() {
+  let data_dir = std::env::temp_dir().join(format!("rust-bank-json-output-{}", std::process::id()));
+
+  // create account with unknown command typed first, then exit
+  let output = run_menu(&data_dir, "9\nx\n0\neur\n2\n");
+  assert_eq!(output.len(), 1);
+  assert_eq!(output[0]["command"], "create-account");
+  assert_eq!(output[0]["status"], "ok");
+
+  let card_number = output[0]["result"]["card_number"].as_str().unwrap();
+  let pin = output[0]["result"]["pin"].as_str().unwrap();
+
+  // login, balance, history, exit
+  let output = run_menu(&data_dir, &format!("1\n{card_number}\n{pin}\n0\n1\n7\n"));
+  let commands: Vec<&str> = output
+    .iter()
+    .map(|object| object["command"].as_str().unwrap())
+    .collect();
+  assert_eq!(commands, ["login", "balance", "history"]);
+  assert_eq!(output[1]["result"]["balance"], "0.00");
+
+  std::fs::remove_dir_all(&data_dir).unwrap();
+}
+
+#[test]
+fn should_print_startup_errors_as_json() {
+  let data_dir = std::env::temp_dir().join(format!("rust-bank-json-startup-{}", std::process::id()));
+  std::fs::create_dir_all(&data_dir).unwrap();
+
+  let rates = data_dir.join("rates.json");
+  std::fs::write(&rates, "{").unwrap();
+
+  // data directory can't be created inside a file
+  let not_a_dir = rates.join("data");
+
+  let cases: [&[&OsStr]; 2] = [
+    &["memory".as_ref(), "--data-dir".as_ref(), data_dir.as_os_str()],
+    &["json".as_ref(), "--data-dir".as_ref(), not_a_dir.as_os_str()],
+  ];
+
+  for args in cases {
+    let (exit_code, objects) = run(args, "");
+    assert_eq!(exit_code, Some(1), "{args:?}");
+    assert_eq!(objects.len(), 1, "{args:?}");
+    assert_eq!(objects[0]["status"], "error", "{args:?}");
+  }
+
+  std::fs::remove_dir_all(&data_dir).unwrap();
+}